@@ -1,7 +1,7 @@
 use log::info;
 use solvers::{
     common::{Dir, DEPTH, HEIGHT, WIDTH},
-    volume,
+    volume::{self, StepOutcome},
 };
 use std::collections::HashMap;
 use three_d::*;
@@ -78,16 +78,23 @@ pub fn demo_3d() {
     let mut solving = false;
     let mut step_once = false;
     let mut step_to_sol = false;
+    // the next solution is further than what is explored in a frame
+    let mut seeking_solution = false;
     let mut transparency = true;
     let mut monochrome = false;
     let mut show_numbers = true;
     let mut solve_sums = false;
     let mut solver_mode_toggle = false;
+    // 0 for no limit
+    let mut max_solutions = 0;
+    // time spent solving, in ms, for the remaining time estimate
+    let mut solving_time = 0.;
 
     let mut gui = three_d::GUI::new(&context);
 
     window.render_loop(move |mut frame_input| {
         let mut panel_width = 0.0;
+        let progress = solver.progress();
         gui.update(
             &mut frame_input.events,
             frame_input.accumulated_time,
@@ -112,16 +119,29 @@ pub fn demo_3d() {
                         .clicked()
                     {
                         solving = false;
+                        seeking_solution = false;
                     };
                     if ui.add(Button::new("Step once")).clicked() {
                         step_once = true;
                         solving = false;
                     };
                     ui.add(Checkbox::new(&mut step_to_sol, "Step to solutions only"));
+                    ui.label(format!(
+                        "Explored: {:.2}% ({} nodes), remaining: ~{:.0?}",
+                        progress.fraction * 100.,
+                        progress.nodes,
+                        progress
+                            .remaining(std::time::Duration::from_secs_f64(solving_time / 1000.))
+                            .unwrap_or_default()
+                    ));
+                    ui.add(
+                        Slider::new(&mut max_solutions, 0..=10)
+                            .text("Stop after solutions (0: no limit)"),
+                    );
                     if ui
                         .add(Checkbox::new(
                             &mut solve_sums,
-                            "Toggle solving with side sums (restarts the solver; stepping past the last solution will freeze the app until the solver has explored all configurations)",
+                            "Toggle solving with side sums (restarts the solver; stepping past the last solution takes a while, as the solver explores all configurations)",
                         ))
                         .clicked()
                     {
@@ -153,10 +173,13 @@ pub fn demo_3d() {
 
         if solver_mode_toggle {
             solving = false;
+            seeking_solution = false;
             solver_mode_toggle = false;
             solver = volume::solver(!solve_sums);
+            solving_time = 0.;
         }
-        // would freeze the UI, also there are too few solutions for animation to make sense
+        solver.set_max_solutions((max_solutions > 0).then_some(max_solutions as u128));
+        // too few solutions for animation to make sense
         if solve_sums && step_to_sol {
             solving = false;
         }
@@ -265,18 +288,24 @@ pub fn demo_3d() {
         frame_input.screen().render(&camera, &bounding_box, &[]);
         frame_input.screen().write(|| gui.render()).unwrap();
 
-        if solving || step_once {
+        if solving || step_once || seeking_solution {
             step_once = false;
+            solving_time += frame_input.elapsed_time;
             let delta = frame_input.accumulated_time - last_step_time;
-            if !solver.done() && (step_freq == 120 || delta >= 1000. / step_freq as f64) {
+            if !solver.done()
+                && (seeking_solution || step_freq == 120 || delta >= 1000. / step_freq as f64)
+            {
                 if step_to_sol {
-                    solver.step_to_solution();
+                    // bounded, so a far away solution doesn't freeze the UI
+                    seeking_solution =
+                        solver.step_to_solution_n(NODES_PER_FRAME) == StepOutcome::Paused;
                 } else {
+                    seeking_solution = false;
                     solver.step();
                 }
                 last_step_time = frame_input.accumulated_time;
                 if solver.done() {
-                    info!("solved, {} solutions found", solver.solutions().len());
+                    info!("solved, {} solutions found", solver.solution_count());
                 }
             }
         }
@@ -285,6 +314,9 @@ pub fn demo_3d() {
     });
 }
 
+// search nodes explored per frame at most, when stepping to a solution
+const NODES_PER_FRAME: u64 = 20_000;
+
 fn block_gm(
     context: &Context,
     r: u8,
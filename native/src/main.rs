@@ -1,18 +1,137 @@
 use log::info;
-use solvers::{constraints, volume};
+use solvers::common::{BLOCKS, DEPTH, HEIGHT, WIDTH};
+use solvers::volume::{Backend, Retention, Symmetry};
+use solvers::{constraints, parallel, puzzle_stats, shard, sink::NdjsonSink, two_phase, volume};
+use std::time::Instant;
 
 pub fn main() {
     env_logger::init();
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("shard") {
+        if let Err(e) = run_shard(&args[1..]) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if args.first().map(String::as_str) == Some("exact-cover") {
+        // the alternative search backend, sequential, e.g. to check the counts
+        let mut solver = volume::solver(args.get(1).map(String::as_str) == Some("--shape-only"));
+        solver.set_retention(Retention::Nothing);
+        solver.run(Backend::ExactCover);
+        info!("total solutions: {}", solver.solution_count());
+        return;
+    }
+    if args.first().map(String::as_str) == Some("stats") {
+        let dims = [HEIGHT as usize, WIDTH as usize, DEPTH as usize];
+        info!(
+            "puzzle stats:\n{}",
+            puzzle_stats::compute(dims, &BLOCKS, 100)
+        );
+        return;
+    }
+    if args.first().map(String::as_str) == Some("two-phase") {
+        // the packings of the shapes first, then the orientations of the blocks within each
+        let packings = two_phase::solve(Symmetry::Rotations);
+        for (index, packing) in packings.iter().enumerate() {
+            if packing.assignments > 0 {
+                info!("packing {}: {} solutions", index, packing.assignments);
+            }
+        }
+        info!(
+            "total solutions: {} from {} packings",
+            packings
+                .iter()
+                .map(|packing| packing.assignments)
+                .sum::<u128>(),
+            packings.len()
+        );
+        return;
+    }
+
     constraints::solve(100);
 
     let mut solver = volume::solver(false);
-    while solver.step() {}
+    // solutions are written as they're found, so a long run can be inspected or interrupted;
+    // each run starts the file over, as it numbers its solutions from 1
+    solver.add_sink(Box::new(
+        NdjsonSink::create("solutions.ndjson").expect("cannot create solutions.ndjson"),
+    ));
+    solver.enable_stats();
+    // progress is told against the estimate: the subtrees searched in parallel are below the tree
+    // explored first, which holds many of the nodes, so the share of them merged tells little
+    let estimate = solver.estimate_nodes(1000, 0);
+    info!("estimated search tree size: {:.0} nodes", estimate);
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let start = Instant::now();
+    let mut last_percent = 0;
+    parallel::solve_with_progress(&mut solver, 2, threads, |progress| {
+        let progress = progress.of_estimate(estimate);
+        let percent = (progress.fraction * 100.) as u32;
+        if percent > last_percent {
+            last_percent = percent;
+            info!(
+                "explored {}% ({} of ~{:.0} nodes), remaining ~{:.0?}",
+                percent,
+                progress.nodes,
+                estimate,
+                progress.remaining(start.elapsed()).unwrap_or_default()
+            );
+        }
+    });
     for solution in solver.solutions() {
         info!("solution:\n{}", solution);
     }
-    info!("total solutions: {}", solver.solutions().len());
+    info!("total solutions: {}", solver.solution_count());
+    let orbits = solver.orbits(Symmetry::Rotations);
+    info!(
+        "counting the rotations of the box: {}",
+        orbits.iter().map(|orbit| orbit.size).sum::<usize>()
+    );
+    if let Some(stats) = solver.stats() {
+        info!("search stats:\n{}", stats);
+    }
 
     // uncomment to run the visualization
     // gui::demo_3d();
 }
+
+const SHARD_USAGE: &str = "usage:
+  native shard export <split depth> <dir> [--shape-only]
+  native shard solve <unit file>
+  native shard merge <dir> <out.ndjson>";
+
+// spread a search over separate runs: export work units, solve each one, merge the results
+fn run_shard(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    match args.as_slice() {
+        ["export", depth, dir, rest @ ..] => {
+            let shape_only = rest == ["--shape-only"];
+            if !shape_only && !rest.is_empty() {
+                return Err(SHARD_USAGE.into());
+            }
+            let total = shard::export(dir, depth.parse()?, shape_only)?;
+            info!("{} work units written to {}", total, dir);
+        }
+        ["solve", unit] => {
+            let report = shard::solve(unit)?;
+            info!(
+                "unit {}/{}: {} solutions",
+                report.index + 1,
+                report.total,
+                report.solution_count
+            );
+        }
+        ["merge", dir, out] => {
+            let report = shard::merge(dir, out)?;
+            info!(
+                "{} units merged into {}: {} solutions",
+                report.shards, out, report.solution_count
+            );
+            info!("search stats:\n{}", report.stats);
+        }
+        _ => return Err(SHARD_USAGE.into()),
+    }
+    Ok(())
+}
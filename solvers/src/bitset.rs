@@ -0,0 +1,149 @@
+// A set of puzzle cells, one bit per cell, in the order of the cell indices.
+// Block masks are built once at the origin and used at any offset, without being copied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct BitSet {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl BitSet {
+    pub(crate) fn new(len: usize) -> BitSet {
+        BitSet {
+            words: vec![0; len.div_ceil(64)],
+            len,
+        }
+    }
+
+    pub(crate) fn insert(&mut self, i: usize) {
+        self.words[i / 64] |= 1 << (i % 64);
+    }
+
+    pub(crate) fn contains(&self, i: usize) -> bool {
+        self.words[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    pub(crate) fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    // drop the trailing empty words, so a mask at the origin only spans the words it covers
+    pub(crate) fn trimmed(mut self) -> BitSet {
+        while self.words.last() == Some(&0) {
+            self.words.pop();
+        }
+        self
+    }
+
+    // the first cell not in the set, from `start` on; `len` if there is none
+    pub(crate) fn first_empty_from(&self, start: usize) -> usize {
+        let mut k = start / 64;
+        if k >= self.words.len() {
+            return self.len;
+        }
+        // the cells before start count as taken
+        let mut free = !self.words[k] & (!0 << (start % 64));
+        loop {
+            if free != 0 {
+                return (k * 64 + free.trailing_zeros() as usize).min(self.len);
+            }
+            k += 1;
+            if k == self.words.len() {
+                return self.len;
+            }
+            free = !self.words[k];
+        }
+    }
+
+    // whether any cell of `mask`, moved by `offset`, is in the set
+    pub(crate) fn intersects_at(&self, mask: &BitSet, offset: usize) -> bool {
+        self.span(mask, offset)
+            .any(|k| self.words[k] & mask.word_at(k, offset) != 0)
+    }
+
+    // whether the cells of `target` are all in the set once `mask`, moved by `offset`, is added
+    pub(crate) fn covers_with(&self, mask: &BitSet, offset: usize, target: &BitSet) -> bool {
+        target.words.iter().enumerate().all(|(k, &word)| {
+            let missing = word & !self.words[k];
+            missing == 0 || missing & !mask.word_at(k, offset) == 0
+        })
+    }
+
+    pub(crate) fn insert_at(&mut self, mask: &BitSet, offset: usize) {
+        for k in self.span(mask, offset) {
+            self.words[k] |= mask.word_at(k, offset);
+        }
+    }
+
+    pub(crate) fn remove_at(&mut self, mask: &BitSet, offset: usize) {
+        for k in self.span(mask, offset) {
+            self.words[k] &= !mask.word_at(k, offset);
+        }
+    }
+
+    // the words of the set `mask` can reach once moved by `offset`
+    fn span(&self, mask: &BitSet, offset: usize) -> std::ops::Range<usize> {
+        let first = offset / 64;
+        first.min(self.words.len())..(first + mask.words.len() + 1).min(self.words.len())
+    }
+
+    // word `k` of the set moved by `offset`
+    fn word_at(&self, k: usize, offset: usize) -> u64 {
+        let (shift_words, shift_bits) = (offset / 64, offset % 64);
+        let word = |i: usize| {
+            k.checked_sub(shift_words + i)
+                .and_then(|j| self.words.get(j))
+                .copied()
+                .unwrap_or(0)
+        };
+        if shift_bits == 0 {
+            word(0)
+        } else {
+            word(0) << shift_bits | word(1) >> (64 - shift_bits)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn masks_at_an_offset() {
+        let mut mask = BitSet::new(200);
+        for i in [0, 1, 63, 64, 70] {
+            mask.insert(i);
+        }
+        let mask = mask.trimmed();
+
+        let mut set = BitSet::new(200);
+        for i in 0..60 {
+            set.insert(i);
+        }
+        assert_eq!(set.first_empty_from(0), 60);
+        assert!(!set.intersects_at(&mask, 60));
+        set.insert_at(&mask, 60);
+        for i in [60, 61, 123, 124, 130] {
+            assert!(set.contains(i));
+        }
+        assert!(!set.contains(62));
+        assert!(set.intersects_at(&mask, 59));
+        assert_eq!(set.first_empty_from(0), 62);
+        assert_eq!(set.first_empty_from(123), 125);
+
+        let mut target = BitSet::new(200);
+        target.insert(10);
+        target.insert(130);
+        assert!(set.covers_with(&mask, 60, &target));
+        set.remove_at(&mask, 60);
+        assert!(!set.contains(130));
+        assert!(set.covers_with(&mask, 60, &target));
+        assert!(!set.covers_with(&mask, 0, &target));
+        assert_eq!(set.first_empty_from(0), 60);
+
+        for i in 0..200 {
+            set.insert(i);
+        }
+        assert_eq!(set.first_empty_from(0), 200);
+        assert_eq!(set.first_empty_from(199), 200);
+    }
+}
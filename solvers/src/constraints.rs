@@ -117,79 +117,130 @@ fn combinations_to_n(faces: &[Face], long: u8, short: u8, n: u8) -> Vec<Vec<Face
 }
 
 fn form_a_rectangle(long: u8, short: u8, faces: &[Face]) -> bool {
-    let size = long as usize * short as usize;
-    let mut candidates = vec![((0, 0), vec![0; size], faces.to_vec())];
-    while !candidates.is_empty() {
-        let mut new_candidates = vec![];
-        for (start_point, state, candidates) in &candidates {
-            for i in 0..candidates.len() {
-                let mut rem = candidates.clone();
-                let face = rem.remove(i);
-                match place(
-                    long,
-                    short,
-                    start_point,
-                    state,
-                    face.long,
-                    face.short,
-                    face.value,
-                ) {
-                    Some((Some(new_start_point), new_state)) => {
-                        new_candidates.push((new_start_point, new_state, rem.clone()))
-                    }
-                    Some((None, final_state)) => {
-                        if rem.is_empty() {
-                            trace!(
-                                "Possible rectangle {} x {} for [ {}]",
-                                long,
-                                short,
-                                faces_to_string(faces)
-                            );
-                            trace!("{}", state_to_string(&final_state, long, short));
+    let region = vec![true; long as usize * short as usize];
+    let pieces = faces
+        .iter()
+        .map(|face| vec![(face.long as usize, face.short as usize)])
+        .collect_vec();
+    match tile_region(long as usize, short as usize, &region, &pieces, true) {
+        Some(tiling) => {
+            trace!(
+                "Possible rectangle {} x {} for [ {}]",
+                long,
+                short,
+                faces_to_string(faces)
+            );
+            let state = tiling
+                .iter()
+                .map(|&piece| faces[piece - 1].value)
+                .collect_vec();
+            trace!("{}", state_to_string(&state, long, short));
+            true
+        }
+        None => {
+            trace!(
+                "No possible rectangle {} x {} for [ {}]",
+                long,
+                short,
+                faces_to_string(faces)
+            );
+            false
+        }
+    }
+}
+
+/// Tile the `region` cells of a grid of `columns` x `rows` cells (row by row) with rectangles,
+/// taking at most one of the rectangles of each piece, in either orientation;
+/// with `use_all`, exactly one of each piece.
+/// Returns the tiling, as the piece covering each cell, numbered from 1; 0 outside the region.
+pub(crate) fn tile_region(
+    columns: usize,
+    rows: usize,
+    region: &[bool],
+    pieces: &[Vec<(usize, usize)>],
+    use_all: bool,
+) -> Option<Vec<usize>> {
+    let mut tiling = Tiling {
+        columns,
+        rows,
+        covered: region.iter().map(|&cell| !cell).collect_vec(),
+        pieces: vec![0; columns * rows],
+        used: vec![false; pieces.len()],
+    };
+    tiling
+        .tile_from(0, pieces, use_all)
+        .then_some(tiling.pieces)
+}
+
+// a partial tiling of a region
+struct Tiling {
+    columns: usize,
+    rows: usize,
+    // the cells outside the region count as covered
+    covered: Vec<bool>,
+    // the piece covering each cell, from 1
+    pieces: Vec<usize>,
+    used: Vec<bool>,
+}
+
+impl Tiling {
+    // the first cell left to cover, from `start` on, is the top left corner of its rectangle
+    fn tile_from(&mut self, start: usize, pieces: &[Vec<(usize, usize)>], use_all: bool) -> bool {
+        let Some(cell) = (start..self.covered.len()).find(|&cell| !self.covered[cell]) else {
+            return !use_all || self.used.iter().all(|&used| used);
+        };
+        // the pieces left cannot cover the cells left
+        let left = self.covered[cell..].iter().filter(|&&c| !c).count();
+        let coverable: usize = pieces
+            .iter()
+            .zip(&self.used)
+            .filter(|(_, &used)| !used)
+            .map(|(rects, _)| rects.iter().map(|(a, b)| a * b).max().unwrap_or(0))
+            .sum();
+        if coverable < left {
+            return false;
+        }
+
+        let (x, y) = (cell % self.columns, cell / self.columns);
+        let mut tried: Vec<&Vec<(usize, usize)>> = vec![];
+        for (piece, rects) in pieces.iter().enumerate() {
+            // the same rectangles as a piece already tried here lead to the same tilings
+            if self.used[piece] || tried.contains(&rects) {
+                continue;
+            }
+            tried.push(rects);
+            self.used[piece] = true;
+            for &(a, b) in rects {
+                for (dx, dy) in [(a, b), (b, a)].into_iter().unique() {
+                    if self.fits(x, y, dx, dy) {
+                        self.cover(x, y, dx, dy, piece + 1);
+                        if self.tile_from(cell, pieces, use_all) {
                             return true;
                         }
-                    }
-                    None => (),
-                }
-                if face.long != face.short {
-                    match place(
-                        long,
-                        short,
-                        start_point,
-                        state,
-                        face.short,
-                        face.long,
-                        face.value,
-                    ) {
-                        Some((Some(new_start_point), new_state)) => {
-                            new_candidates.push((new_start_point, new_state, rem.clone()))
-                        }
-                        Some((None, final_state)) => {
-                            if rem.is_empty() {
-                                trace!(
-                                    "Possible rectangle {} x {} for [ {}]",
-                                    long,
-                                    short,
-                                    faces_to_string(faces)
-                                );
-                                trace!("{}", state_to_string(&final_state, long, short));
-                                return true;
-                            }
-                        }
-                        None => (),
+                        self.cover(x, y, dx, dy, 0);
                     }
                 }
             }
+            self.used[piece] = false;
+        }
+        false
+    }
+
+    fn fits(&self, x: usize, y: usize, dx: usize, dy: usize) -> bool {
+        x + dx <= self.columns
+            && y + dy <= self.rows
+            && (y..y + dy).all(|j| (x..x + dx).all(|i| !self.covered[j * self.columns + i]))
+    }
+
+    // piece 0 uncovers the rectangle
+    fn cover(&mut self, x: usize, y: usize, dx: usize, dy: usize, piece: usize) {
+        for j in y..y + dy {
+            for i in x..x + dx {
+                self.covered[j * self.columns + i] = piece != 0;
+                self.pieces[j * self.columns + i] = piece;
+            }
         }
-        candidates = new_candidates;
     }
-    trace!(
-        "No possible rectangle {} x {} for [ {}]",
-        long,
-        short,
-        faces_to_string(faces)
-    );
-    false
 }
 
 fn faces_to_string(faces: &[Face]) -> String {
@@ -212,40 +263,6 @@ fn state_to_string(state: &[u8], long: u8, short: u8) -> String {
     result
 }
 
-fn place(
-    rect_long: u8,
-    rect_short: u8,
-    start_point: &(u8, u8),
-    state: &[u8],
-    piece_x: u8,
-    piece_y: u8,
-    piece_val: u8,
-) -> Option<(Option<(u8, u8)>, Vec<u8>)> {
-    let x_start = start_point.0;
-    let x_end = x_start + piece_x;
-    let y_start = start_point.1;
-    let y_end = y_start + piece_y;
-    if x_end <= rect_long && y_end <= rect_short {
-        let mut new_state = state.to_vec();
-        for j in y_start..y_end {
-            for i in x_start..x_end {
-                let idx = (j * rect_long + i) as usize;
-                if state[idx] != 0 {
-                    return None;
-                }
-                new_state[idx] = piece_val;
-            }
-        }
-        let new_start_point = new_state
-            .iter()
-            .position(|&e| e == 0)
-            .map(|idx| (idx as u8 % rect_long, idx as u8 / rect_long));
-        Some((new_start_point, new_state))
-    } else {
-        None
-    }
-}
-
 fn find_corner<'a>(
     faces_s: &'a [Face],
     faces_m: &'a [Face],
@@ -624,4 +641,23 @@ mod test {
             ]
         ));
     }
+
+    #[test]
+    fn tile_region_with_some_pieces() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        // an L of 3 x 2 cells: X X X / X . .
+        let region = [true, true, true, true, false, false];
+        let tiling = tile_region(3, 2, &region, &[vec![(1, 1)], vec![(3, 1), (2, 2)]], false);
+        assert_eq!(tiling, Some(vec![2, 2, 2, 1, 0, 0]));
+        assert!(tile_region(3, 2, &region, &[vec![(1, 1)], vec![(2, 2)]], false).is_none());
+        assert!(tile_region(3, 2, &region, &[vec![(2, 1)], vec![(2, 1)]], false).is_some());
+        assert!(tile_region(
+            3,
+            2,
+            &region,
+            &[vec![(2, 1)], vec![(2, 1)], vec![(1, 1)]],
+            true
+        )
+        .is_none());
+    }
 }
@@ -0,0 +1,87 @@
+use crate::bitset::BitSet;
+use std::collections::HashSet;
+
+// marks the slots in use, in the word of the side sums
+const USED: u64 = 1 << 63;
+
+// A bounded table of search states known to have no completion: the cells taken, the blocks
+// left to place and the side sums; the rest of the state follows from those.
+// States are found by their Zobrist key; a new dead end replaces the one in its slot.
+// A state only matches a dead end when equal to it, whatever the keys,
+// so collisions cost searches and never solutions.
+#[derive(Debug)]
+pub(crate) struct DeadEnds {
+    // words per slot: the key, the side sums, the cells taken, then the blocks left
+    stride: usize,
+    cell_words: usize,
+    // all the slots in a row, so a lookup mostly reads a single cache line;
+    // as many as a power of two, so the low bits of a key pick its slot
+    slots: Vec<u64>,
+}
+
+impl DeadEnds {
+    pub(crate) fn new(capacity: usize, cells: usize, blocks: usize) -> DeadEnds {
+        let cell_words = cells.div_ceil(64);
+        let stride = 2 + cell_words + blocks.div_ceil(64);
+        DeadEnds {
+            stride,
+            cell_words,
+            slots: vec![0; capacity.max(1).next_power_of_two() * stride],
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.slots.len() / self.stride
+    }
+
+    // where the slot of the key starts
+    fn start(&self, key: u64) -> usize {
+        (key as usize & (self.capacity() - 1)) * self.stride
+    }
+
+    pub(crate) fn contains(
+        &self,
+        key: u64,
+        occupied: &BitSet,
+        rem: &HashSet<usize>,
+        face_sums: &[u8; 6],
+    ) -> bool {
+        let start = self.start(key);
+        let slot = &self.slots[start..start + self.stride];
+        if slot[0] != key || slot[1] != sums_word(face_sums) {
+            return false;
+        }
+        let (cells, blocks) = slot[2..].split_at(self.cell_words);
+        cells == occupied.words()
+            && blocks.iter().map(|word| word.count_ones()).sum::<u32>() == rem.len() as u32
+            && rem
+                .iter()
+                .all(|&block_id| blocks[block_id / 64] & 1 << (block_id % 64) != 0)
+    }
+
+    pub(crate) fn insert(
+        &mut self,
+        key: u64,
+        occupied: &BitSet,
+        rem: &HashSet<usize>,
+        face_sums: &[u8; 6],
+    ) {
+        let start = self.start(key);
+        let slot = &mut self.slots[start..start + self.stride];
+        slot[0] = key;
+        slot[1] = sums_word(face_sums);
+        let (cells, blocks) = slot[2..].split_at_mut(self.cell_words);
+        cells.copy_from_slice(occupied.words());
+        blocks.fill(0);
+        for &block_id in rem {
+            blocks[block_id / 64] |= 1 << (block_id % 64);
+        }
+    }
+}
+
+fn sums_word(face_sums: &[u8; 6]) -> u64 {
+    face_sums
+        .iter()
+        .fold(0, |word, &sum| word << 8 | sum as u64)
+        | USED
+}
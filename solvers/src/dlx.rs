@@ -0,0 +1,295 @@
+//! Knuth's Algorithm X, with dancing links: finds all the sets of rows of a 0/1 matrix
+//! covering each primary column exactly once, and each secondary column at most once.
+
+/// Extra constraints on the rows of a partial cover, checked as the search goes
+pub trait Constraint {
+    /// Whether `row` can join the partial cover; if so, it is now part of it
+    fn push(&mut self, row: usize) -> bool;
+
+    /// `row`, the last one successfully pushed, leaves the partial cover
+    fn pop(&mut self, row: usize);
+}
+
+/// No constraint besides the exact cover
+impl Constraint for () {
+    fn push(&mut self, _row: usize) -> bool {
+        true
+    }
+
+    fn pop(&mut self, _row: usize) {}
+}
+
+/// An exact cover problem, as a sparse matrix of doubly linked nodes.
+/// Node 0 is the root, nodes `1..=columns` the column headers, the rest the 1s of the matrix.
+pub struct Dlx {
+    left: Vec<usize>,
+    right: Vec<usize>,
+    up: Vec<usize>,
+    down: Vec<usize>,
+    // the header node of the column of each node
+    header: Vec<usize>,
+    // the row of each node; unused for the root and headers
+    row: Vec<usize>,
+    // the number of rows left in each column, by header node
+    size: Vec<usize>,
+    rows: usize,
+}
+
+impl Dlx {
+    /// Columns `0..primary` are to be covered exactly once,
+    /// columns `primary..primary + secondary` at most once.
+    pub fn new(primary: usize, secondary: usize) -> Dlx {
+        let columns = primary + secondary;
+        let mut dlx = Dlx {
+            left: (0..=columns).map(|i| i.wrapping_sub(1)).collect(),
+            right: (0..=columns).map(|i| i + 1).collect(),
+            up: (0..=columns).collect(),
+            down: (0..=columns).collect(),
+            header: (0..=columns).collect(),
+            row: vec![0; columns + 1],
+            size: vec![0; columns + 1],
+            rows: 0,
+        };
+        // the root links the primary columns only, the secondary ones link to themselves
+        dlx.left[0] = primary;
+        dlx.right[primary] = 0;
+        for c in primary + 1..=columns {
+            dlx.left[c] = c;
+            dlx.right[c] = c;
+        }
+        dlx
+    }
+
+    /// Add a row with 1s in the given columns, returning its number; rows are numbered from 0
+    pub fn add_row(&mut self, columns: &[usize]) -> usize {
+        let row = self.rows;
+        self.rows += 1;
+        let first = self.left.len();
+        for (i, &column) in columns.iter().enumerate() {
+            let header = column + 1;
+            let node = first + i;
+            self.left.push(if i == 0 {
+                first + columns.len() - 1
+            } else {
+                node - 1
+            });
+            self.right.push(if i == columns.len() - 1 {
+                first
+            } else {
+                node + 1
+            });
+            self.up.push(self.up[header]);
+            self.down.push(header);
+            self.header.push(header);
+            self.row.push(row);
+            let last = self.up[header];
+            self.down[last] = node;
+            self.up[header] = node;
+            self.size[header] += 1;
+        }
+        row
+    }
+
+    /// Take the row as part of all the covers to come, e.g. to search below a partial cover.
+    /// The row must not conflict with the rows already selected, and no row is to be added after.
+    pub fn select(&mut self, row: usize) {
+        let node = (self.size.len()..self.row.len())
+            .find(|&node| self.row[node] == row)
+            .expect("no such row");
+        self.cover(self.header[node]);
+        let mut j = self.right[node];
+        while j != node {
+            self.cover(self.header[j]);
+            j = self.right[j];
+        }
+    }
+
+    /// Hand every exact cover satisfying `constraint` to `on_solution`, as the rows picked,
+    /// until it returns false. Returns whether the search ran to the end.
+    pub fn search<C, F>(&mut self, constraint: &mut C, mut on_solution: F) -> bool
+    where
+        C: Constraint,
+        F: FnMut(&[usize]) -> bool,
+    {
+        let mut partial = vec![];
+        self.search_below(&mut partial, constraint, &mut on_solution)
+    }
+
+    fn search_below<C, F>(
+        &mut self,
+        partial: &mut Vec<usize>,
+        constraint: &mut C,
+        on_solution: &mut F,
+    ) -> bool
+    where
+        C: Constraint,
+        F: FnMut(&[usize]) -> bool,
+    {
+        if self.right[0] == 0 {
+            return on_solution(partial);
+        }
+        // the column with the fewest rows left
+        let mut column = self.right[0];
+        let mut c = self.right[column];
+        while c != 0 {
+            if self.size[c] < self.size[column] {
+                column = c;
+            }
+            c = self.right[c];
+        }
+        if self.size[column] == 0 {
+            return true;
+        }
+
+        self.cover(column);
+        let mut r = self.down[column];
+        while r != column {
+            let row = self.row[r];
+            if constraint.push(row) {
+                partial.push(row);
+                let mut j = self.right[r];
+                while j != r {
+                    self.cover(self.header[j]);
+                    j = self.right[j];
+                }
+                let go_on = self.search_below(partial, constraint, on_solution);
+                let mut j = self.left[r];
+                while j != r {
+                    self.uncover(self.header[j]);
+                    j = self.left[j];
+                }
+                partial.pop();
+                constraint.pop(row);
+                if !go_on {
+                    self.uncover(column);
+                    return false;
+                }
+            }
+            r = self.down[r];
+        }
+        self.uncover(column);
+        true
+    }
+
+    // take the column out of the header list, and its rows out of the other columns
+    fn cover(&mut self, column: usize) {
+        let (l, r) = (self.left[column], self.right[column]);
+        self.right[l] = r;
+        self.left[r] = l;
+        let mut i = self.down[column];
+        while i != column {
+            let mut j = self.right[i];
+            while j != i {
+                let (u, d) = (self.up[j], self.down[j]);
+                self.down[u] = d;
+                self.up[d] = u;
+                self.size[self.header[j]] -= 1;
+                j = self.right[j];
+            }
+            i = self.down[i];
+        }
+    }
+
+    // undo `cover`, in reverse order
+    fn uncover(&mut self, column: usize) {
+        let mut i = self.up[column];
+        while i != column {
+            let mut j = self.left[i];
+            while j != i {
+                self.size[self.header[j]] += 1;
+                let (u, d) = (self.up[j], self.down[j]);
+                self.down[u] = j;
+                self.up[d] = j;
+                j = self.left[j];
+            }
+            i = self.up[i];
+        }
+        let (l, r) = (self.left[column], self.right[column]);
+        self.right[l] = column;
+        self.left[r] = column;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use itertools::Itertools;
+
+    // Knuth's example from the dancing links paper
+    fn knuth_example() -> Dlx {
+        let mut dlx = Dlx::new(7, 0);
+        for row in [
+            vec![2, 4, 5],
+            vec![0, 3, 6],
+            vec![1, 2, 5],
+            vec![0, 3],
+            vec![1, 6],
+            vec![3, 4, 6],
+        ] {
+            dlx.add_row(&row);
+        }
+        dlx
+    }
+
+    #[test]
+    fn solve_knuth_example() {
+        let mut solutions = vec![];
+        assert!(knuth_example().search(&mut (), |rows| {
+            solutions.push(rows.iter().copied().sorted().collect_vec());
+            true
+        }));
+        assert_eq!(solutions, vec![vec![0, 3, 4]]);
+
+        let mut dlx = knuth_example();
+        dlx.select(4);
+        let mut count = 0;
+        dlx.search(&mut (), |rows| {
+            assert!(!rows.contains(&4));
+            count += 1;
+            true
+        });
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn secondary_columns_are_covered_at_most_once() {
+        // two dominoes in a 1x3 strip, or one domino and a monomino; column 3 is optional
+        let mut dlx = Dlx::new(3, 1);
+        dlx.add_row(&[0, 1, 3]);
+        dlx.add_row(&[1, 2, 3]);
+        dlx.add_row(&[0]);
+        dlx.add_row(&[2]);
+        dlx.add_row(&[1]);
+        let mut solutions = vec![];
+        dlx.search(&mut (), |rows| {
+            solutions.push(rows.iter().copied().sorted().collect_vec());
+            true
+        });
+        assert_eq!(
+            solutions.into_iter().sorted().collect_vec(),
+            vec![vec![0, 3], vec![1, 2], vec![2, 3, 4]]
+        );
+    }
+
+    #[test]
+    fn constraint_prunes_rows() {
+        struct NoRow2;
+        impl Constraint for NoRow2 {
+            fn push(&mut self, row: usize) -> bool {
+                row != 2
+            }
+            fn pop(&mut self, _row: usize) {}
+        }
+        let mut dlx = Dlx::new(3, 0);
+        dlx.add_row(&[0, 1]);
+        dlx.add_row(&[2]);
+        dlx.add_row(&[0]);
+        dlx.add_row(&[1]);
+        let mut count = 0;
+        dlx.search(&mut NoRow2, |_| {
+            count += 1;
+            true
+        });
+        assert_eq!(count, 1);
+    }
+}
@@ -1,3 +1,15 @@
+mod bitset;
 pub mod common;
 pub mod constraints;
+mod dead_ends;
+pub mod dlx;
+pub mod observer;
+pub mod parallel;
+mod placement;
+pub mod puzzle_stats;
+pub mod shard;
+pub mod sink;
+pub mod stats;
+mod symmetry;
+pub mod two_phase;
 pub mod volume;
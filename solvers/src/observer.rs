@@ -0,0 +1,40 @@
+use crate::common::Dir;
+use crate::volume::{BlockInPuzzle, Solution};
+
+/// Why a block could not be placed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rejection {
+    // the block would go past the puzzle bounds
+    SticksOut,
+    // the block would overlap an already placed block
+    Overlap,
+    // the block face would push the side sum over the target
+    SideSumTooBig(Dir),
+    // the block face would fill the side, but its sum would stay under the target
+    SideFullSumShort(Dir),
+    // the block face would bring the side sum to the target, with the side not full yet
+    SideSumReachedNotFull(Dir),
+    // the remaining blocks could not bring the side sum to the target over the free side area
+    SideSumUnreachable(Dir),
+    // the remaining block faces could not tile the uncovered cells of the side
+    SideUntileable(Dir),
+    // a symmetry of the puzzle maps the placement, or the packing it completes,
+    // to one searched instead
+    Symmetric,
+    // the block would wall off an empty region no set of the remaining blocks can fill
+    DeadSpace,
+    // the placement leads to a state already searched to no solution
+    KnownDeadEnd,
+}
+
+/// Hooks into the events of a volume search; all methods do nothing by default.
+/// `depth` is the number of blocks in the stack once the event has happened.
+pub trait SearchObserver {
+    fn block_placed(&mut self, _placement: &BlockInPuzzle, _depth: usize) {}
+
+    fn block_removed(&mut self, _placement: &BlockInPuzzle, _depth: usize) {}
+
+    fn placement_rejected(&mut self, _placement: &BlockInPuzzle, _rejection: Rejection) {}
+
+    fn solution_found(&mut self, _solution: &Solution) {}
+}
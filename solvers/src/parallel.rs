@@ -0,0 +1,163 @@
+use crate::volume::{Progress, Solution, Solver};
+use log::debug;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Condvar, Mutex};
+use std::thread;
+
+// solutions the search of a subtree gets ahead of the merge by, at most
+const SOLUTION_BUFFER: usize = 1024;
+// subtrees started ahead of the merge, at most, per thread
+const WINDOW_PER_THREAD: usize = 4;
+
+// what the search of a subtree hands to the merge
+enum Found {
+    // a solution, for the sinks and observers, as soon as found
+    Solution(Solution),
+    // the search, once over
+    Searched(Box<Solver>),
+}
+
+/// Run the search of `solver`'s puzzle from scratch on `threads` threads.
+///
+/// The search tree is cut at `split_depth` placed blocks, and each subtree below the cut
+/// is searched independently. The results of the subtrees are taken in by `solver`
+/// in the order a sequential search would find them, as soon as all the subtrees before
+/// are done; sinks and observers get them as soon as found, in that order. Retention policy,
+/// solution limit (all threads stop once reached), sinks, observers (solutions only)
+/// and stats of `solver` apply.
+pub fn solve(solver: &mut Solver, split_depth: usize, threads: usize) {
+    solve_with_progress(solver, split_depth, threads, |_| {});
+}
+
+/// Same as `solve`, reporting the progress of the search each time a subtree is taken in
+pub fn solve_with_progress<F: FnMut(Progress)>(
+    solver: &mut Solver,
+    split_depth: usize,
+    threads: usize,
+    mut on_progress: F,
+) {
+    let puzzle = solver.puzzle();
+    let settings = solver.settings();
+    let (frontier, explorer) = puzzle.explore_frontier(split_depth, solver.stats().is_some());
+    debug!(
+        "{} subproblems at depth {}, on {} threads",
+        frontier.len(),
+        split_depth,
+        threads
+    );
+    // the solutions of the tree above the subtrees are those of their stacks
+    let taken = explorer.solution_count();
+    // the blocks already placed by `solver` are placed again by the explorer, and counted then
+    solver.forget_nodes();
+    solver.absorb(explorer, taken);
+
+    // a channel per subtree searched, read in order by the merge: a search ahead of the merge
+    // waits once its channel is full, and a subtree too far ahead waits to be started,
+    // rather than solutions piling up
+    let window = threads.max(1) * WINDOW_PER_THREAD;
+    let (register, registered) = mpsc::channel();
+    let next = AtomicUsize::new(0);
+    // the subtrees taken in so far
+    let merged = (Mutex::new(0), Condvar::new());
+    // set once the solution limit is reached
+    let stop = AtomicBool::new(false);
+    thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            let register = register.clone();
+            let (puzzle, frontier, next, merged, stop) =
+                (&puzzle, &frontier, &next, &merged, &stop);
+            scope.spawn(move || loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= frontier.len() {
+                    break;
+                }
+                let (lock, taken_in) = merged;
+                let mut count = lock.lock().unwrap();
+                while i >= *count + window && !stop.load(Ordering::Relaxed) {
+                    count = taken_in.wait(count).unwrap();
+                }
+                drop(count);
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                let (results, received) = mpsc::sync_channel(SOLUTION_BUFFER);
+                // the merge is over once it stops listening
+                if register.send((i, received)).is_err() {
+                    break;
+                }
+                let mut sub = puzzle
+                    .subproblem(&frontier[i])
+                    .expect("frontier stacks are built by the search");
+                sub.apply(settings);
+                if settings.streams() {
+                    while let Some(solution) = sub.next_solution(Some(stop)) {
+                        if results.send(Found::Solution(solution)).is_err() {
+                            return;
+                        }
+                    }
+                } else {
+                    while !stop.load(Ordering::Relaxed) && sub.step() {}
+                }
+                if results.send(Found::Searched(Box::new(sub))).is_err() {
+                    return;
+                }
+            });
+        }
+        drop(register);
+
+        let mut receivers = BTreeMap::new();
+        'merge: for i in 0..frontier.len() {
+            let received = loop {
+                if let Some(received) = receivers.remove(&i) {
+                    break received;
+                }
+                match registered.recv() {
+                    Ok((j, received)) => {
+                        receivers.insert(j, received);
+                    }
+                    // the searches are all over, one of them having panicked
+                    Err(_) => break 'merge,
+                }
+            };
+            let mut streamed = 0;
+            loop {
+                match received.recv() {
+                    Ok(Found::Solution(solution)) => {
+                        streamed += 1;
+                        solver.absorb_solution(solution);
+                    }
+                    Ok(Found::Searched(sub)) => {
+                        solver.absorb(*sub, streamed);
+                        break;
+                    }
+                    // the search of the subtree panicked, which the scope reports
+                    Err(_) => break 'merge,
+                }
+                if solver.limit_reached() {
+                    break;
+                }
+            }
+            let (lock, taken_in) = &merged;
+            *lock.lock().unwrap() = i + 1;
+            if solver.limit_reached() {
+                stop.store(true, Ordering::Relaxed);
+            }
+            taken_in.notify_all();
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+            // the search is done up to where the next subtree starts
+            on_progress(Progress {
+                fraction: frontier
+                    .get(i + 1)
+                    .map_or(1., |stack| puzzle.fraction(stack, 0)),
+                nodes: solver.progress().nodes,
+            });
+        }
+        // the searches still going on find their channel closed, or the stop set, and end
+        drop(registered);
+        drop(receivers);
+    });
+    solver.finish();
+}
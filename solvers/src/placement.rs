@@ -0,0 +1,447 @@
+use crate::bitset::BitSet;
+use crate::common::{Block, Dir};
+use crate::symmetry::PuzzleSymmetry;
+use crate::volume::{Rng, Symmetry};
+use itertools::Itertools;
+use std::cmp::Reverse;
+
+// a rotated block which stays within the puzzle when placed at a given anchor cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Fit {
+    pub(crate) block_id: usize,
+    pub(crate) rot_id: usize,
+    // the sides of the puzzle the block shows on, by `Dir` index
+    pub(crate) touched: [bool; 6],
+    // false when a symmetry of the puzzle maps the placement to one searched instead
+    pub(crate) canonical: bool,
+    // the Zobrist key of the placement: of the cells it fills, and of its block
+    pub(crate) key: u64,
+}
+
+// All the placements of the blocks of a puzzle, computed once per puzzle.
+// Read-only, shared by all the solvers of the puzzle, whatever their thread.
+#[derive(Debug)]
+pub(crate) struct Placements {
+    // for each base block and rotation, the cells it fills when placed at position 0
+    masks: Vec<Vec<BitSet>>,
+    // the cells of each side of the puzzle, by `Dir` index
+    side_masks: Vec<BitSet>,
+    // for each anchor cell, the fitting block rotations, by block id then rotation id,
+    // which is the order the search tries them in
+    fits: Vec<Vec<Fit>>,
+    // with a target sum, what sets of blocks can bring to each side
+    side_sums: Option<SideSumTable>,
+    // with a target sum, the Zobrist keys of the side sums, by `Dir` index then sum
+    sum_keys: Vec<Vec<u64>>,
+    dims: [usize; 3],
+    // by block id then rotation id, the block size along each axis
+    sizes: Vec<Vec<[usize; 3]>>,
+    symmetry: Symmetry,
+    // the symmetries the search is cut down by
+    symmetries: Vec<PuzzleSymmetry>,
+    // the block whose placements are cut down to one per orbit under the symmetries:
+    // the one with the most placements, as blank shapes or with the face values the search
+    // tells apart, so the one placed early in the most branches
+    anchor: usize,
+}
+
+// For each set of blocks and each side of the puzzle, the (area, value) totals the faces
+// of those blocks can show on that side, each block showing at most one face.
+// As bitsets over the values `0..=target`, one per area `0..=side area`.
+#[derive(Debug)]
+struct SideSumTable {
+    // words per bitset
+    words: usize,
+    // areas per side, by `Dir` index
+    areas: [usize; 6],
+    // where the bitsets of each side start, by `Dir` index
+    offsets: [usize; 6],
+    // bitsets by set of blocks (as a mask of block ids), then side, then area
+    reachable: Vec<Vec<u64>>,
+}
+
+// sets of blocks are masks of block ids; past that many blocks the table is not worth it
+const MAX_SIDE_SUMS_BLOCKS: usize = 12;
+
+impl SideSumTable {
+    fn new(
+        height: usize,
+        width: usize,
+        depth: usize,
+        target: u8,
+        rot_blocks: &[Vec<Block>],
+    ) -> Self {
+        let words = (target as usize + 1).div_ceil(64);
+        let mut areas = [0; 6];
+        for dir in [Dir::Front, Dir::Back] {
+            areas[dir as usize] = height * width;
+        }
+        for dir in [Dir::Left, Dir::Right] {
+            areas[dir as usize] = height * depth;
+        }
+        for dir in [Dir::Top, Dir::Bottom] {
+            areas[dir as usize] = width * depth;
+        }
+        let mut offsets = [0; 6];
+        for idx in 1..6 {
+            offsets[idx] = offsets[idx - 1] + (areas[idx - 1] + 1) * words;
+        }
+        let set_len = offsets[5] + (areas[5] + 1) * words;
+
+        // each set is the set without its highest block, plus that block
+        let mut reachable = vec![vec![0; set_len]];
+        for idx in 0..6 {
+            // nothing shown: area 0, value 0
+            reachable[0][offsets[idx]] = 1;
+        }
+        for set in 1..1usize << rot_blocks.len() {
+            let block_id = usize::BITS as usize - 1 - set.leading_zeros() as usize;
+            let smaller = &reachable[set & !(1 << block_id)];
+            let mut sums = smaller.clone();
+            for idx in 0..6 {
+                let faces = rot_blocks[block_id]
+                    .iter()
+                    .map(|block| {
+                        (
+                            block.faces[idx].area() as usize,
+                            block.faces[idx].value as usize,
+                        )
+                    })
+                    .unique()
+                    .collect_vec();
+                let side = offsets[idx];
+                for (area, value) in faces {
+                    for from in 0..=areas[idx].saturating_sub(area) {
+                        let src = &smaller[side + from * words..side + (from + 1) * words];
+                        let to = side + (from + area) * words;
+                        or_shifted(&mut sums[to..to + words], src, value);
+                    }
+                }
+            }
+            reachable.push(sums);
+        }
+
+        SideSumTable {
+            words,
+            areas,
+            offsets,
+            reachable,
+        }
+    }
+
+    fn reachable(&self, set: usize, dir: Dir, area: usize, value: usize) -> bool {
+        let idx = dir as usize;
+        if area > self.areas[idx] || value >= self.words * 64 {
+            return false;
+        }
+        let word = self.reachable[set][self.offsets[idx] + area * self.words + value / 64];
+        word & (1 << (value % 64)) != 0
+    }
+}
+
+// or `src`, moved up by `shift` bits, into `dst`; the bits moved past the end are dropped
+fn or_shifted(dst: &mut [u64], src: &[u64], shift: usize) {
+    let (shift_words, shift_bits) = (shift / 64, shift % 64);
+    for (j, word) in dst.iter_mut().skip(shift_words).enumerate() {
+        *word |= src[j] << shift_bits;
+        if shift_bits != 0 && j > 0 {
+            *word |= src[j - 1] >> (64 - shift_bits);
+        }
+    }
+}
+
+impl Placements {
+    pub(crate) fn new(
+        height: usize,
+        width: usize,
+        depth: usize,
+        target: Option<u8>,
+        rot_blocks: &[Vec<Block>],
+        symmetry: Symmetry,
+    ) -> Self {
+        let volume = height * width * depth;
+        let index = |x: usize, y: usize, z: usize| (z * width + y) * height + x;
+
+        let masks = rot_blocks
+            .iter()
+            .map(|rots| {
+                rots.iter()
+                    .map(|block| {
+                        let mut mask = BitSet::new(volume);
+                        for z in 0..(block.depth as usize).min(depth) {
+                            for y in 0..(block.width as usize).min(width) {
+                                for x in 0..(block.height as usize).min(height) {
+                                    mask.insert(index(x, y, z));
+                                }
+                            }
+                        }
+                        mask.trimmed()
+                    })
+                    .collect_vec()
+            })
+            .collect_vec();
+
+        // fixed, so the keys of a puzzle are the same in every run
+        let mut rng = Rng::new(0);
+        let cell_keys = (0..volume).map(|_| rng.next_u64()).collect_vec();
+        let block_keys = rot_blocks.iter().map(|_| rng.next_u64()).collect_vec();
+        let sum_keys = target.map_or(vec![], |target| {
+            (0..6)
+                .map(|_| (0..=target).map(|_| rng.next_u64()).collect_vec())
+                .collect_vec()
+        });
+
+        let mut side_masks = vec![BitSet::new(volume); 6];
+        let mut fits = vec![vec![]; volume];
+        for z in 0..depth {
+            for y in 0..width {
+                for x in 0..height {
+                    let sides = [
+                        (Dir::Front, z == 0),
+                        (Dir::Back, z == depth - 1),
+                        (Dir::Left, y == 0),
+                        (Dir::Right, y == width - 1),
+                        (Dir::Top, x == height - 1),
+                        (Dir::Bottom, x == 0),
+                    ];
+                    for (dir, on_side) in sides {
+                        if on_side {
+                            side_masks[dir as usize].insert(index(x, y, z));
+                        }
+                    }
+
+                    for (block_id, rots) in rot_blocks.iter().enumerate() {
+                        for (rot_id, block) in rots.iter().enumerate() {
+                            let x_end = x + block.height as usize;
+                            let y_end = y + block.width as usize;
+                            let z_end = z + block.depth as usize;
+                            if x_end > height || y_end > width || z_end > depth {
+                                continue;
+                            }
+                            let mut touched = [false; 6];
+                            touched[Dir::Bottom as usize] = x == 0;
+                            touched[Dir::Top as usize] = x_end == height;
+                            touched[Dir::Left as usize] = y == 0;
+                            touched[Dir::Right as usize] = y_end == width;
+                            touched[Dir::Front as usize] = z == 0;
+                            touched[Dir::Back as usize] = z_end == depth;
+                            let mut key = block_keys[block_id];
+                            for z in z..z_end {
+                                for y in y..y_end {
+                                    for x in x..x_end {
+                                        key ^= cell_keys[index(x, y, z)];
+                                    }
+                                }
+                            }
+                            fits[index(x, y, z)].push(Fit {
+                                block_id,
+                                rot_id,
+                                touched,
+                                canonical: true,
+                                key,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        let side_sums = target
+            .filter(|_| rot_blocks.len() <= MAX_SIDE_SUMS_BLOCKS)
+            .map(|target| SideSumTable::new(height, width, depth, target, rot_blocks));
+
+        let dims = [height, width, depth];
+        let sizes = rot_blocks
+            .iter()
+            .map(|rots| {
+                rots.iter()
+                    .map(|block| {
+                        [
+                            block.height as usize,
+                            block.width as usize,
+                            block.depth as usize,
+                        ]
+                    })
+                    .collect_vec()
+            })
+            .collect_vec();
+        let symmetries = PuzzleSymmetry::all(dims, rot_blocks, target.is_some(), symmetry);
+        let anchor = fits
+            .iter()
+            .flatten()
+            .counts_by(|fit| fit.block_id)
+            .into_iter()
+            .max_by_key(|&(block_id, count)| (count, Reverse(block_id)))
+            .map_or(0, |(block_id, _)| block_id);
+
+        let mut placements = Placements {
+            masks,
+            side_masks,
+            fits,
+            side_sums,
+            sum_keys,
+            dims,
+            sizes,
+            symmetry,
+            symmetries,
+            anchor,
+        };
+        // of each orbit of placements of the anchor block, only the last one in the order of the
+        // cells is searched, by rotation next: the placements cut are those early in the search,
+        // above the largest subtrees
+        for position in 0..volume {
+            for i in 0..placements.fits[position].len() {
+                let fit = placements.fits[position][i];
+                if fit.block_id == placements.anchor {
+                    let canonical = placements.symmetries.iter().all(|symmetry| {
+                        let (rot_id, mapped) =
+                            placements.map(symmetry, fit.block_id, fit.rot_id, position);
+                        (Reverse(position), fit.rot_id) <= (Reverse(mapped), rot_id)
+                    });
+                    placements.fits[position][i].canonical = canonical;
+                }
+            }
+        }
+        placements
+    }
+
+    // the rotation and position of a placed block, once the puzzle is mapped by the symmetry
+    fn map(
+        &self,
+        symmetry: &PuzzleSymmetry,
+        block_id: usize,
+        rot_id: usize,
+        position: usize,
+    ) -> (usize, usize) {
+        symmetry.map(
+            self.dims,
+            self.sizes[block_id][rot_id],
+            block_id,
+            rot_id,
+            position,
+        )
+    }
+
+    // Whether the packing, as (block id, rotation id, position) triples, is the one searched
+    // among its images by the symmetries of the puzzle which leave the anchor block in place.
+    // The others map the anchor block away from the placement searched, see `Fit::canonical`.
+    pub(crate) fn is_canonical(&self, packing: &[(usize, usize, usize)]) -> bool {
+        let Some(&(_, anchor_rot, anchor_position)) = packing
+            .iter()
+            .find(|(block_id, _, _)| *block_id == self.anchor)
+        else {
+            return true;
+        };
+        // packings compare by their placements, in the order of the cells
+        let sorted = |packing: &mut dyn Iterator<Item = (usize, usize, usize)>| {
+            packing
+                .map(|(block_id, rot_id, position)| (position, block_id, rot_id))
+                .sorted()
+                .collect_vec()
+        };
+        let original = sorted(&mut packing.iter().copied());
+        self.symmetries
+            .iter()
+            .filter(|symmetry| {
+                self.map(symmetry, self.anchor, anchor_rot, anchor_position)
+                    == (anchor_rot, anchor_position)
+            })
+            .all(|symmetry| {
+                let image = sorted(&mut packing.iter().map(|&(block_id, rot_id, position)| {
+                    let (rot_id, position) = self.map(symmetry, block_id, rot_id, position);
+                    (block_id, rot_id, position)
+                }));
+                original <= image
+            })
+    }
+
+    pub(crate) fn symmetry(&self) -> Symmetry {
+        self.symmetry
+    }
+
+    pub(crate) fn mask(&self, block_id: usize, rot_id: usize) -> &BitSet {
+        &self.masks[block_id][rot_id]
+    }
+
+    pub(crate) fn side_mask(&self, dir: Dir) -> &BitSet {
+        &self.side_masks[dir as usize]
+    }
+
+    // the block rotations which fit at the anchor; none past the end of the puzzle
+    pub(crate) fn fits(&self, position: usize) -> &[Fit] {
+        self.fits.get(position).map_or(&[], Vec::as_slice)
+    }
+
+    // the blocks as a set of the side sum table, a mask of block ids; none without a table,
+    // which only puzzles of few enough blocks for a mask have
+    pub(crate) fn side_sum_set<'a>(
+        &self,
+        block_ids: impl IntoIterator<Item = &'a usize>,
+    ) -> Option<usize> {
+        self.side_sums.as_ref()?;
+        Some(
+            block_ids
+                .into_iter()
+                .fold(0, |set, &block_id| set | 1 << block_id),
+        )
+    }
+
+    // whether some of the blocks of `set` (a mask of block ids) can show faces covering
+    // exactly `area` on the side, adding up to `value`; always true without a table
+    pub(crate) fn side_sum_reachable(
+        &self,
+        set: usize,
+        dir: Dir,
+        area: usize,
+        value: usize,
+    ) -> bool {
+        self.side_sums
+            .as_ref()
+            .is_none_or(|sums| sums.reachable(set, dir, area, value))
+    }
+
+    // the Zobrist key of the side sums; 0 without a target sum
+    pub(crate) fn sums_key(&self, sums: &[u8; 6]) -> u64 {
+        self.sum_keys
+            .iter()
+            .zip(sums)
+            .fold(0, |key, (keys, &sum)| key ^ keys[sum as usize])
+    }
+
+    // Where the subtree below the stack, as (block id, rotation id, position) triples, starts
+    // in the search order, as a fraction of the tree below its first `floor` placements.
+    // The siblings of a placement are taken to be the searched fits at its anchor, of the blocks
+    // not placed yet, and their subtrees of the same size.
+    pub(crate) fn fraction(&self, stack: &[(usize, usize, usize)], floor: usize) -> f64 {
+        let mut placed = vec![false; self.masks.len()];
+        let mut fraction = 0.;
+        let mut share = 1.;
+        for (depth, &(block_id, rot_id, position)) in stack.iter().enumerate() {
+            if depth >= floor {
+                let siblings = self
+                    .fits(position)
+                    .iter()
+                    .filter(|fit| fit.canonical && !placed[fit.block_id])
+                    .map(|fit| (fit.block_id, fit.rot_id));
+                let (mut rank, mut count) = (0, 0);
+                for sibling in siblings {
+                    if sibling < (block_id, rot_id) {
+                        rank += 1;
+                    }
+                    count += 1;
+                }
+                share /= count.max(1) as f64;
+                fraction += share * rank as f64;
+            }
+            placed[block_id] = true;
+        }
+        fraction
+    }
+
+    // where the block rotation is among the fits of the anchor, if it fits there
+    pub(crate) fn find(&self, position: usize, block_id: usize, rot_id: usize) -> Option<usize> {
+        self.fits(position)
+            .binary_search_by_key(&(block_id, rot_id), |fit| (fit.block_id, fit.rot_id))
+            .ok()
+    }
+}
@@ -0,0 +1,152 @@
+use crate::common::Block;
+use crate::two_phase;
+use crate::volume::{self, Puzzle, Symmetry};
+use itertools::Itertools;
+use std::fmt;
+
+/// The counts of solutions of the puzzle, as given in the README
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PuzzleStats {
+    /// Packings of the blocks as blank shapes, rotations of the box discounted
+    pub shape_packings: u128,
+    /// How the packings of shapes turn into packings of blocks with their face values
+    pub blocks: Vec<BlockOrientations>,
+    /// Packings of the blocks with their face values, whether they meet the side targets or not
+    pub packings: u128,
+    /// Packings meeting the side targets
+    pub solutions: u128,
+    /// Solutions which show different values on the sides, discounting the free turns of the
+    /// blocks
+    pub distinct_solutions: u128,
+}
+
+/// The orientations of a block a packing of shapes stands for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockOrientations {
+    pub label: &'static str,
+    /// Whether the block has a square base, then looking the same after a quarter turn
+    pub square_base: bool,
+    /// How many of the 24 rotations of the block look the same as blank shapes: the 4 turns
+    /// about one axis, twice for a square base
+    pub multiplicity: u64,
+    /// How many rotations of the block in its place in a solution show the same values on the
+    /// sides, at most over the solutions: 2 for a block showing a single face, turned over in
+    /// its plane, more for a block showing none; 1 when no solution has the block turn freely
+    pub free_turns: u64,
+}
+
+/// The counts of solutions of the puzzle of the blocks in a box of `dims`, each side summing
+/// to `target`, rotations of the box discounted
+pub fn compute(dims: [usize; 3], blocks: &[Block], target: u8) -> PuzzleStats {
+    of_puzzles(
+        &volume::puzzle_of(dims, blocks, None, Symmetry::Rotations),
+        &volume::puzzle_of(dims, blocks, Some(target), Symmetry::Rotations),
+    )
+}
+
+// the counts for a puzzle, as blank shapes and with its values
+fn of_puzzles(shapes: &Puzzle, values: &Puzzle) -> PuzzleStats {
+    let packings = two_phase::solve_puzzle(shapes, values);
+    let solver = shapes.solver();
+    let blocks = shapes
+        .rot_blocks()
+        .iter()
+        .enumerate()
+        .map(|(block_id, rots)| {
+            let multiplicity = solver.rot_multiplicity(block_id, 0);
+            BlockOrientations {
+                label: rots[0].label,
+                // 4 turns about an axis of each of the 6 or 3 distinct orientations
+                square_base: multiplicity > 4,
+                multiplicity,
+                free_turns: packings
+                    .iter()
+                    .filter(|packing| packing.assignments > 0)
+                    .map(|packing| packing.free_turns[block_id])
+                    .max()
+                    .unwrap_or(1),
+            }
+        })
+        .collect_vec();
+    let shape_packings = packings.len() as u128;
+    PuzzleStats {
+        shape_packings,
+        packings: shape_packings
+            * blocks
+                .iter()
+                .map(|block| block.multiplicity as u128)
+                .product::<u128>(),
+        blocks,
+        solutions: packings.iter().map(|packing| packing.assignments).sum(),
+        distinct_solutions: packings.iter().map(|packing| packing.distinct).sum(),
+    }
+}
+
+impl fmt::Display for PuzzleStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "shape packings: {}", self.shape_packings)?;
+        writeln!(f, "orientations per packed shape:")?;
+        for block in &self.blocks {
+            writeln!(
+                f,
+                "  {}: {}{}{}",
+                block.label,
+                block.multiplicity,
+                if block.square_base {
+                    " (square base)"
+                } else {
+                    ""
+                },
+                if block.free_turns > 1 {
+                    format!(", turns {} ways in place", block.free_turns)
+                } else {
+                    String::new()
+                }
+            )?;
+        }
+        writeln!(f, "packings with face values: {}", self.packings)?;
+        writeln!(f, "solutions: {}", self.solutions)?;
+        writeln!(f, "distinct solutions: {}", self.distinct_solutions)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::{BLOCKS, DEPTH, HEIGHT, WIDTH};
+    use crate::volume::test::blocks_2x_2x1x1_to_sum;
+
+    #[test]
+    fn stats_of_2x_2x1x1_in_2x2x1_to_sum() {
+        let stats = compute([2, 2, 1], &blocks_2x_2x1x1_to_sum(), 12);
+        assert_eq!(stats.shape_packings, 1);
+        assert!(stats
+            .blocks
+            .iter()
+            .all(|block| block.square_base && block.multiplicity == 8));
+        assert_eq!(stats.packings, 64);
+        assert_eq!(stats.solutions, 1);
+        // each block shows five faces of different values, so no turn of it makes a twin
+        assert_eq!(stats.distinct_solutions, 1);
+        assert!(stats.blocks.iter().all(|block| block.free_turns == 1));
+    }
+
+    #[test]
+    fn stats_of_puzzle() {
+        let stats = compute(
+            [HEIGHT as usize, WIDTH as usize, DEPTH as usize],
+            &BLOCKS,
+            100,
+        );
+        assert_eq!(stats.shape_packings, 1074);
+        assert_eq!(stats.packings, 2_252_341_248);
+        assert_eq!(stats.solutions, 4);
+        assert_eq!(stats.distinct_solutions, 2);
+        // the long square shows a single face, turning over in its plane
+        for block in &stats.blocks {
+            let free_turns = if block.label == "long_square" { 2 } else { 1 };
+            assert_eq!(block.free_turns, free_turns, "{}", block.label);
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use crate::sink::{NdjsonSink, SolutionRecord};
+use crate::stats::SearchStats;
+use crate::volume::{self, BlockInPuzzle, BlockOrder, CellOrder, Puzzle, Symmetry};
+use itertools::Itertools;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// A part of a volume search, to be solved by its own process:
+/// the subtree below a fixed stack of placements
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkUnit {
+    pub shape_only: bool,
+    // rank of the subtree in the order of a sequential search
+    pub index: usize,
+    pub total: usize,
+    pub prefix: Vec<BlockInPuzzle>,
+    pub symmetry: Symmetry,
+    pub order: CellOrder,
+    pub block_order: BlockOrder,
+}
+
+/// What solving a work unit found, written next to its solutions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShardReport {
+    pub index: usize,
+    pub total: usize,
+    pub solution_count: u128,
+    pub stats: SearchStats,
+}
+
+/// The combined result of all the work units of a search
+#[derive(Debug, Clone)]
+pub struct MergeReport {
+    pub shards: usize,
+    pub solution_count: u128,
+    pub stats: SearchStats,
+}
+
+/// Cut the search tree at `split_depth` placed blocks and write one work unit file
+/// per subtree in `dir`, returning the number of units.
+/// Like `volume::solver`, counts the packings which are rotations of each other once.
+pub fn export<P: AsRef<Path>>(dir: P, split_depth: usize, shape_only: bool) -> io::Result<usize> {
+    write_units(
+        &volume::puzzle(shape_only, Symmetry::Rotations),
+        shape_only,
+        split_depth,
+        dir.as_ref(),
+    )
+}
+
+/// Solve the work unit in `unit_path`, writing its solutions (`.ndjson`)
+/// and its report (`.report.json`) next to it
+pub fn solve<P: AsRef<Path>>(unit_path: P) -> io::Result<ShardReport> {
+    let unit: WorkUnit = serde_json::from_reader(BufReader::new(File::open(&unit_path)?))?;
+    solve_unit(
+        &volume::puzzle_in_order(unit.shape_only, unit.symmetry, unit.order, unit.block_order),
+        &unit,
+        unit_path.as_ref(),
+    )
+}
+
+/// Combine the shards solved in `dir` into a single solution file `out`, numbered as
+/// a sequential search would; fails if any shard of the search is missing.
+pub fn merge<P: AsRef<Path>, Q: AsRef<Path>>(dir: P, out: Q) -> io::Result<MergeReport> {
+    let mut reports = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.to_string_lossy().ends_with(".report.json") {
+            let report: ShardReport = serde_json::from_reader(BufReader::new(File::open(&path)?))?;
+            reports.push((report, path));
+        }
+    }
+    reports.sort_by_key(|(report, _)| report.index);
+
+    let total = reports.first().map_or(0, |(report, _)| report.total);
+    if total == 0
+        || reports.len() != total
+        || reports
+            .iter()
+            .enumerate()
+            .any(|(i, (report, _))| report.index != i || report.total != total)
+    {
+        let found = reports.iter().map(|(report, _)| report.index).collect_vec();
+        return Err(invalid(format!(
+            "incomplete shards: expected {} units, found {:?}",
+            total, found
+        )));
+    }
+
+    let mut writer = BufWriter::new(File::create(out)?);
+    let mut stats = SearchStats::default();
+    let mut offset = 0;
+    for (report, path) in &reports {
+        let solutions = File::open(solutions_path(&unit_path(path)))?;
+        let mut count = 0;
+        for line in BufReader::new(solutions).lines() {
+            let mut record: SolutionRecord = serde_json::from_str(&line?)?;
+            record.index += offset;
+            serde_json::to_writer(&mut writer, &record)?;
+            writer.write_all(b"\n")?;
+            count += 1;
+        }
+        if count != report.solution_count {
+            return Err(invalid(format!(
+                "shard {}: {} solutions reported, {} written",
+                report.index, report.solution_count, count
+            )));
+        }
+        offset += count;
+        stats.merge(&report.stats);
+    }
+    writer.flush()?;
+    Ok(MergeReport {
+        shards: total,
+        solution_count: offset,
+        stats,
+    })
+}
+
+fn write_units(
+    puzzle: &Puzzle,
+    shape_only: bool,
+    split_depth: usize,
+    dir: &Path,
+) -> io::Result<usize> {
+    fs::create_dir_all(dir)?;
+    let frontier = puzzle.frontier(split_depth);
+    let total = frontier.len();
+    for (index, prefix) in frontier.into_iter().enumerate() {
+        let unit = WorkUnit {
+            shape_only,
+            index,
+            total,
+            prefix,
+            symmetry: puzzle.symmetry(),
+            order: puzzle.order(),
+            block_order: puzzle.block_order(),
+        };
+        let path = dir.join(format!("unit_{:05}.json", index));
+        serde_json::to_writer(BufWriter::new(File::create(path)?), &unit)?;
+    }
+    debug!("{} work units at depth {} in {:?}", total, split_depth, dir);
+    Ok(total)
+}
+
+fn solve_unit(puzzle: &Puzzle, unit: &WorkUnit, unit_path: &Path) -> io::Result<ShardReport> {
+    let mut solver = puzzle.solver();
+    // registered before the prefix is placed, as it may be a solution already
+    solver.add_sink(Box::new(NdjsonSink::create(solutions_path(unit_path))?));
+    let mut solver = solver
+        .restricted_to(&unit.prefix)
+        .ok_or_else(|| invalid(format!("{:?} is not a unit of this puzzle", unit_path)))?;
+    solver.enable_stats();
+    let solution_count = solver.count_solutions();
+    let report = ShardReport {
+        index: unit.index,
+        total: unit.total,
+        solution_count,
+        stats: solver.stats().cloned().unwrap_or_default(),
+    };
+    let report_path = unit_path.with_extension("report.json");
+    serde_json::to_writer(BufWriter::new(File::create(report_path)?), &report)?;
+    Ok(report)
+}
+
+fn solutions_path(unit_path: &Path) -> PathBuf {
+    unit_path.with_extension("ndjson")
+}
+
+// the unit file a report was written for
+fn unit_path(report_path: &Path) -> PathBuf {
+    let name = report_path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    report_path.with_file_name(name.replace(".report.json", ".json"))
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::volume::test::rot_blocks_4x_2x1x1;
+    use crate::volume::Solver;
+
+    #[test]
+    fn shards_of_4x_2x1x1_in_2x2x2_merge_to_sequential() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let rot_blocks = rot_blocks_4x_2x1x1();
+        let puzzle = Solver::new(2, 2, 2, None, rot_blocks.clone()).puzzle();
+        let dir = std::env::temp_dir().join(format!("shards_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let total = write_units(&puzzle, true, 2, &dir).unwrap();
+        assert!(total > 1);
+        for index in 0..total {
+            let unit_path = dir.join(format!("unit_{:05}.json", index));
+            let unit: WorkUnit = serde_json::from_reader(File::open(&unit_path).unwrap()).unwrap();
+            assert_eq!(unit.index, index);
+            solve_unit(&puzzle, &unit, &unit_path).unwrap();
+        }
+        fs::remove_file(dir.join("unit_00000.report.json")).unwrap();
+        assert!(merge(&dir, dir.join("all.ndjson")).is_err());
+        solve_unit(
+            &puzzle,
+            &serde_json::from_reader(File::open(dir.join("unit_00000.json")).unwrap()).unwrap(),
+            &dir.join("unit_00000.json"),
+        )
+        .unwrap();
+        let merged = merge(&dir, dir.join("all.ndjson")).unwrap();
+
+        let mut sequential = Solver::new(2, 2, 2, None, rot_blocks);
+        sequential.set_retention(volume::Retention::Nothing);
+        sequential.init();
+        let expected = sequential.collect_vec();
+        let content = fs::read_to_string(dir.join("all.ndjson")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let records = content
+            .lines()
+            .map(|l| serde_json::from_str::<SolutionRecord>(l).unwrap())
+            .collect_vec();
+
+        assert_eq!(merged.shards, total);
+        assert_eq!(merged.solution_count, 216);
+        assert_eq!(merged.stats.solutions, 216);
+        assert_eq!(records.len(), expected.len());
+        for (i, (record, solution)) in records.iter().zip(&expected).enumerate() {
+            assert_eq!(record.index, i as u128 + 1);
+            assert_eq!(
+                record.blocks.iter().map(|b| b.position).collect_vec(),
+                solution
+                    .placements()
+                    .iter()
+                    .map(|b| b.position())
+                    .collect_vec()
+            );
+        }
+    }
+
+    #[test]
+    fn shards_of_full_packings_merge() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let rot_blocks = rot_blocks_4x_2x1x1();
+        let block_count = rot_blocks.len();
+        let puzzle = Solver::new(2, 2, 2, None, rot_blocks).puzzle();
+        let dir = std::env::temp_dir().join(format!("full_shards_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        // every unit is a packing, found as its prefix is placed
+        let total = write_units(&puzzle, true, block_count, &dir).unwrap();
+        for index in 0..total {
+            let unit_path = dir.join(format!("unit_{:05}.json", index));
+            let unit: WorkUnit = serde_json::from_reader(File::open(&unit_path).unwrap()).unwrap();
+            assert_eq!(
+                solve_unit(&puzzle, &unit, &unit_path)
+                    .unwrap()
+                    .solution_count,
+                1
+            );
+        }
+        let merged = merge(&dir, dir.join("all.ndjson"));
+        let content = fs::read_to_string(dir.join("all.ndjson"));
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(merged.unwrap().solution_count, 216);
+        assert_eq!(content.unwrap().lines().count(), 216);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::volume::{Solution, Solver};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Receives the solutions of a search, as soon as they are found
+pub trait SolutionSink {
+    fn accept(&mut self, solver: &Solver, solution: &Solution) -> std::io::Result<()>;
+}
+
+/// A block of a solution, as written by `NdjsonSink`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlacedBlock {
+    pub block_id: usize,
+    pub rot_id: usize,
+    pub position: usize,
+    pub label: String,
+    pub x: usize,
+    pub y: usize,
+    pub z: usize,
+    pub height: u8,
+    pub width: u8,
+    pub depth: u8,
+}
+
+/// A line of a file written by `NdjsonSink`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SolutionRecord {
+    // 1-based rank of the solution in the search
+    pub index: u128,
+    pub blocks: Vec<PlacedBlock>,
+}
+
+/// Appends one JSON line per solution to a file, flushed right away,
+/// so the results of a search survive it being interrupted.
+pub struct NdjsonSink {
+    out: BufWriter<File>,
+}
+
+impl NdjsonSink {
+    /// Open `path` for appending, creating the file if needed
+    pub fn append<P: AsRef<Path>>(path: P) -> std::io::Result<NdjsonSink> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(NdjsonSink {
+            out: BufWriter::new(file),
+        })
+    }
+
+    /// Open `path` for writing, replacing the file if it exists
+    pub fn create<P: AsRef<Path>>(path: P) -> std::io::Result<NdjsonSink> {
+        Ok(NdjsonSink {
+            out: BufWriter::new(File::create(path)?),
+        })
+    }
+}
+
+impl SolutionSink for NdjsonSink {
+    fn accept(&mut self, solver: &Solver, solution: &Solution) -> std::io::Result<()> {
+        let record = SolutionRecord {
+            index: solver.solution_count(),
+            blocks: solver
+                .solution_blocks(solution)
+                .into_iter()
+                .zip(solution.placements())
+                .map(|((block, block_id, x, y, z), bip)| PlacedBlock {
+                    block_id,
+                    rot_id: bip.rot_id(),
+                    position: bip.position(),
+                    label: block.label.to_string(),
+                    x,
+                    y,
+                    z,
+                    height: block.height,
+                    width: block.width,
+                    depth: block.depth,
+                })
+                .collect(),
+        };
+        serde_json::to_writer(&mut self.out, &record)?;
+        self.out.write_all(b"\n")?;
+        self.out.flush()
+    }
+}
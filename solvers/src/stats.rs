@@ -0,0 +1,255 @@
+use crate::common::Dir;
+use crate::observer::{Rejection, SearchObserver};
+use crate::volume::{BlockInPuzzle, Solution};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+/// Counters collected along a volume search
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchStats {
+    // blocks placed, i.e. nodes of the search tree
+    pub nodes: u64,
+    // placements tried, successful or not
+    pub attempts: u64,
+    pub solutions: u64,
+    pub max_depth: usize,
+    // indexed by `rejection_index`; a map is too slow to update on every rejection
+    rejections: Vec<u64>,
+    // indexed by stack depth; time spent with that many blocks placed.
+    // Empty on wasm, where there is no clock.
+    pub time_per_depth: Vec<Duration>,
+    // indexed by block id; times the block was taken off the stack
+    pub backtracks: Vec<u64>,
+    #[serde(skip)]
+    depth: usize,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    last_event: Option<Instant>,
+}
+
+const DIRS: [Dir; 6] = [
+    Dir::Front,
+    Dir::Back,
+    Dir::Left,
+    Dir::Right,
+    Dir::Top,
+    Dir::Bottom,
+];
+
+const REJECTION_KINDS: usize = 5 + 5 * DIRS.len();
+
+fn rejection_index(rejection: Rejection) -> usize {
+    match rejection {
+        Rejection::SticksOut => 0,
+        Rejection::Overlap => 1,
+        Rejection::SideSumTooBig(dir) => 2 + dir as usize,
+        Rejection::SideFullSumShort(dir) => 8 + dir as usize,
+        Rejection::SideSumReachedNotFull(dir) => 14 + dir as usize,
+        Rejection::DeadSpace => 20,
+        Rejection::SideSumUnreachable(dir) => 21 + dir as usize,
+        Rejection::SideUntileable(dir) => 27 + dir as usize,
+        Rejection::Symmetric => 33,
+        Rejection::KnownDeadEnd => 34,
+    }
+}
+
+fn rejection_at(index: usize) -> Rejection {
+    match index {
+        0 => Rejection::SticksOut,
+        1 => Rejection::Overlap,
+        2..8 => Rejection::SideSumTooBig(DIRS[index - 2]),
+        8..14 => Rejection::SideFullSumShort(DIRS[index - 8]),
+        14..20 => Rejection::SideSumReachedNotFull(DIRS[index - 14]),
+        20 => Rejection::DeadSpace,
+        21..27 => Rejection::SideSumUnreachable(DIRS[index - 21]),
+        27..33 => Rejection::SideUntileable(DIRS[index - 27]),
+        33 => Rejection::Symmetric,
+        _ => Rejection::KnownDeadEnd,
+    }
+}
+
+impl SearchStats {
+    /// The rejections which happened at least once, with their count
+    pub fn rejections(&self) -> Vec<(Rejection, u64)> {
+        self.rejections
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(i, &count)| (rejection_at(i), count))
+            .collect_vec()
+    }
+
+    /// Rejections summed over sides, by kind of rejection
+    pub fn rejections_by_reason(&self) -> Vec<(&'static str, u64)> {
+        let mut counts: HashMap<&'static str, u64> = HashMap::new();
+        for (rejection, count) in self.rejections() {
+            *counts.entry(reason(&rejection)).or_default() += count;
+        }
+        counts
+            .into_iter()
+            .sorted_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)))
+            .collect_vec()
+    }
+
+    /// Rejections caused by a side of the puzzle, by side
+    pub fn rejections_by_side(&self) -> Vec<(Dir, u64)> {
+        let mut counts: HashMap<Dir, u64> = HashMap::new();
+        for (rejection, count) in self.rejections() {
+            if let Some(dir) = side(&rejection) {
+                *counts.entry(dir).or_default() += count;
+            }
+        }
+        counts.into_iter().sorted().collect_vec()
+    }
+
+    /// Add up the counters of another search, e.g. of another part of the same search tree
+    pub fn merge(&mut self, other: &SearchStats) {
+        self.nodes += other.nodes;
+        self.attempts += other.attempts;
+        self.solutions += other.solutions;
+        self.max_depth = self.max_depth.max(other.max_depth);
+        for (count, other_count) in self.rejections.iter_mut().zip(&other.rejections) {
+            *count += other_count;
+        }
+        if self.time_per_depth.len() < other.time_per_depth.len() {
+            self.time_per_depth
+                .resize(other.time_per_depth.len(), Duration::ZERO);
+        }
+        for (time, other_time) in self.time_per_depth.iter_mut().zip(&other.time_per_depth) {
+            *time += *other_time;
+        }
+        if self.backtracks.len() < other.backtracks.len() {
+            self.backtracks.resize(other.backtracks.len(), 0);
+        }
+        for (count, other_count) in self.backtracks.iter_mut().zip(&other.backtracks) {
+            *count += other_count;
+        }
+    }
+
+    pub fn total_time(&self) -> Duration {
+        self.time_per_depth.iter().sum()
+    }
+
+    // charge the time since the previous event to the current depth, where there is a clock,
+    // then move to `depth`
+    fn tick(&mut self, depth: usize) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let now = Instant::now();
+            if let Some(last) = self.last_event {
+                if self.time_per_depth.len() <= self.depth {
+                    self.time_per_depth.resize(self.depth + 1, Duration::ZERO);
+                }
+                self.time_per_depth[self.depth] += now - last;
+            }
+            self.last_event = Some(now);
+        }
+        self.depth = depth;
+        self.max_depth = self.max_depth.max(depth);
+    }
+}
+
+impl Default for SearchStats {
+    fn default() -> Self {
+        SearchStats {
+            nodes: 0,
+            attempts: 0,
+            solutions: 0,
+            max_depth: 0,
+            rejections: vec![0; REJECTION_KINDS],
+            time_per_depth: vec![],
+            backtracks: vec![],
+            depth: 0,
+            #[cfg(not(target_arch = "wasm32"))]
+            last_event: None,
+        }
+    }
+}
+
+fn reason(rejection: &Rejection) -> &'static str {
+    match rejection {
+        Rejection::SticksOut => "sticks out",
+        Rejection::Overlap => "overlap",
+        Rejection::SideSumTooBig(_) => "side sum too big",
+        Rejection::SideFullSumShort(_) => "side full but sum short",
+        Rejection::SideSumReachedNotFull(_) => "side sum reached but side not full",
+        Rejection::DeadSpace => "dead space",
+        Rejection::SideSumUnreachable(_) => "side sum out of reach",
+        Rejection::SideUntileable(_) => "side cannot be tiled",
+        Rejection::Symmetric => "symmetric",
+        Rejection::KnownDeadEnd => "known dead end",
+    }
+}
+
+fn side(rejection: &Rejection) -> Option<Dir> {
+    match rejection {
+        Rejection::SticksOut
+        | Rejection::Overlap
+        | Rejection::DeadSpace
+        | Rejection::Symmetric
+        | Rejection::KnownDeadEnd => None,
+        Rejection::SideSumTooBig(dir)
+        | Rejection::SideFullSumShort(dir)
+        | Rejection::SideSumReachedNotFull(dir)
+        | Rejection::SideSumUnreachable(dir)
+        | Rejection::SideUntileable(dir) => Some(*dir),
+    }
+}
+
+impl SearchObserver for SearchStats {
+    fn block_placed(&mut self, _placement: &BlockInPuzzle, depth: usize) {
+        self.nodes += 1;
+        self.attempts += 1;
+        self.tick(depth);
+    }
+
+    fn block_removed(&mut self, placement: &BlockInPuzzle, depth: usize) {
+        let block_id = placement.block_id();
+        if self.backtracks.len() <= block_id {
+            self.backtracks.resize(block_id + 1, 0);
+        }
+        self.backtracks[block_id] += 1;
+        self.tick(depth);
+    }
+
+    fn placement_rejected(&mut self, _placement: &BlockInPuzzle, rejection: Rejection) {
+        self.attempts += 1;
+        self.rejections[rejection_index(rejection)] += 1;
+    }
+
+    fn solution_found(&mut self, _solution: &Solution) {
+        self.solutions += 1;
+    }
+}
+
+impl fmt::Display for SearchStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "nodes: {}", self.nodes)?;
+        writeln!(f, "placements attempted: {}", self.attempts)?;
+        writeln!(f, "solutions: {}", self.solutions)?;
+        writeln!(f, "max depth: {}", self.max_depth)?;
+        writeln!(f, "time: {:?}", self.total_time())?;
+        writeln!(f, "rejections by reason:")?;
+        for (reason, count) in self.rejections_by_reason() {
+            writeln!(f, "  {}: {}", reason, count)?;
+        }
+        writeln!(f, "rejections by side:")?;
+        for (dir, count) in self.rejections_by_side() {
+            writeln!(f, "  {:?}: {}", dir, count)?;
+        }
+        writeln!(f, "time per depth:")?;
+        for (depth, time) in self.time_per_depth.iter().enumerate() {
+            writeln!(f, "  {}: {:?}", depth, time)?;
+        }
+        writeln!(f, "backtracks per block:")?;
+        for (block_id, count) in self.backtracks.iter().enumerate() {
+            writeln!(f, "  {}: {}", block_id, count)?;
+        }
+        Ok(())
+    }
+}
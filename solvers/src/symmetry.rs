@@ -0,0 +1,208 @@
+use crate::common::{Block, Dir, Face};
+use crate::volume::Symmetry;
+use itertools::Itertools;
+
+// the sides of the box along each axis, low side first:
+// x is the height (Bottom to Top), y the width (Left to Right), z the depth (Front to Back)
+const AXIS_SIDES: [[Dir; 2]; 3] = [
+    [Dir::Bottom, Dir::Top],
+    [Dir::Left, Dir::Right],
+    [Dir::Front, Dir::Back],
+];
+
+// A map of the box onto itself: new axis `j` is old axis `axes[j]`, reversed when `flips[j]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct BoxMap {
+    axes: [usize; 3],
+    flips: [bool; 3],
+}
+
+impl BoxMap {
+    pub(crate) fn new(axes: [usize; 3], flips: [bool; 3]) -> BoxMap {
+        BoxMap { axes, flips }
+    }
+
+    // the map taking the box back where it was
+    pub(crate) fn inverse(&self) -> BoxMap {
+        let mut inverse = BoxMap::new([0; 3], [false; 3]);
+        for j in 0..3 {
+            inverse.axes[self.axes[j]] = j;
+            inverse.flips[self.axes[j]] = self.flips[j];
+        }
+        inverse
+    }
+
+    // all the maps of a box of the given dimensions onto itself, but the identity:
+    // its rotations, and its mirror images too if asked
+    pub(crate) fn all(dims: [usize; 3], mirrors: bool) -> Vec<BoxMap> {
+        (0..3)
+            .permutations(3)
+            .cartesian_product(0..8)
+            .map(|(axes, flips)| BoxMap {
+                axes: [axes[0], axes[1], axes[2]],
+                flips: [flips & 1 != 0, flips & 2 != 0, flips & 4 != 0],
+            })
+            .filter(|map| (0..3).all(|j| dims[j] == dims[map.axes[j]]))
+            .filter(|map| !map.is_identity() && (mirrors || !map.is_mirror()))
+            .collect_vec()
+    }
+
+    // the dimensions of the box once mapped
+    pub(crate) fn map_dims(&self, dims: [usize; 3]) -> [usize; 3] {
+        self.axes.map(|i| dims[i])
+    }
+
+    fn is_identity(&self) -> bool {
+        self.axes == [0, 1, 2] && self.flips == [false; 3]
+    }
+
+    // whether the map turns the box inside out, i.e. cannot be done by rotating it
+    pub(crate) fn is_mirror(&self) -> bool {
+        // an odd permutation swaps two axes
+        let swaps = (0..3).filter(|&j| self.axes[j] != j).count() == 2;
+        let flips = self.flips.iter().filter(|&&flip| flip).count() % 2 == 1;
+        swaps != flips
+    }
+
+    // where a box of `size` with its low corner at `corner` goes, as its new low corner;
+    // within a box of `dims`
+    pub(crate) fn map_corner(
+        &self,
+        dims: [usize; 3],
+        corner: [usize; 3],
+        size: [usize; 3],
+    ) -> [usize; 3] {
+        std::array::from_fn(|j| {
+            let i = self.axes[j];
+            if self.flips[j] {
+                dims[i] - corner[i] - size[i]
+            } else {
+                corner[i]
+            }
+        })
+    }
+
+    // the block as it is once its place in the box is mapped: sizes and faces go along
+    pub(crate) fn map_block(&self, block: &Block) -> Block {
+        let size = [block.height, block.width, block.depth];
+        let mut faces = block.faces.clone();
+        for j in 0..3 {
+            let i = self.axes[j];
+            for side in 0..2 {
+                let from = AXIS_SIDES[i][side];
+                let to = AXIS_SIDES[j][side ^ self.flips[j] as usize];
+                faces[to as usize] = Face {
+                    dir: to,
+                    ..block.faces[from as usize].clone()
+                };
+            }
+        }
+        Block {
+            height: size[self.axes[0]],
+            width: size[self.axes[1]],
+            depth: size[self.axes[2]],
+            faces,
+            label: block.label,
+        }
+    }
+}
+
+// A map of the box which turns every packing into another packing:
+// it turns each rotation of each block into another rotation of the same block
+#[derive(Debug)]
+pub(crate) struct PuzzleSymmetry {
+    map: BoxMap,
+    // by block id then rotation id, the rotation id once mapped
+    rots: Vec<Vec<usize>>,
+}
+
+impl PuzzleSymmetry {
+    // the symmetries of the puzzle among the maps of the box asked for, but the identity;
+    // without values, only the shapes of the blocks matter
+    pub(crate) fn all(
+        dims: [usize; 3],
+        rot_blocks: &[Vec<Block>],
+        with_values: bool,
+        symmetry: Symmetry,
+    ) -> Vec<PuzzleSymmetry> {
+        if symmetry == Symmetry::None {
+            return vec![];
+        }
+        BoxMap::all(dims, symmetry == Symmetry::RotationsAndMirrors)
+            .into_iter()
+            .filter_map(|map| PuzzleSymmetry::new(map, rot_blocks, with_values))
+            .collect_vec()
+    }
+
+    fn new(map: BoxMap, rot_blocks: &[Vec<Block>], with_values: bool) -> Option<PuzzleSymmetry> {
+        let same = |a: &Block, b: &Block| {
+            (a.height, a.width, a.depth) == (b.height, b.width, b.depth)
+                && (!with_values
+                    || a.faces
+                        .iter()
+                        .zip(&b.faces)
+                        .all(|(f, g)| f.value == g.value))
+        };
+        let rots = rot_blocks
+            .iter()
+            .map(|rots| {
+                rots.iter()
+                    .map(|block| {
+                        let mapped = map.map_block(block);
+                        rots.iter().position(|rot| same(rot, &mapped))
+                    })
+                    .collect::<Option<Vec<_>>>()
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(PuzzleSymmetry { map, rots })
+    }
+
+    // the rotation and position of a block of `size` placed in a box of `dims`, once mapped
+    pub(crate) fn map(
+        &self,
+        dims: [usize; 3],
+        size: [usize; 3],
+        block_id: usize,
+        rot_id: usize,
+        position: usize,
+    ) -> (usize, usize) {
+        let [height, width, _] = dims;
+        let corner = [
+            position % height,
+            position / height % width,
+            position / (height * width),
+        ];
+        let [x, y, z] = self.map.map_corner(dims, corner, size);
+        (self.rots[block_id][rot_id], (z * width + y) * height + x)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn maps_of_boxes() {
+        assert_eq!(BoxMap::all([12, 11, 9], false).len(), 3);
+        assert_eq!(BoxMap::all([12, 11, 9], true).len(), 7);
+        assert_eq!(BoxMap::all([2, 2, 1], false).len(), 7);
+        assert_eq!(BoxMap::all([3, 3, 3], false).len(), 23);
+        assert_eq!(BoxMap::all([3, 3, 3], true).len(), 47);
+
+        // a half turn around the height
+        let map = BoxMap {
+            axes: [0, 1, 2],
+            flips: [false, true, true],
+        };
+        assert!(!map.is_mirror());
+        assert_eq!(map.map_corner([4, 3, 2], [1, 0, 0], [2, 1, 1]), [1, 2, 1]);
+
+        // a quarter turn, and back
+        let map = BoxMap::new([1, 0, 2], [true, false, false]);
+        assert_eq!(map.map_corner([4, 3, 2], [1, 0, 1], [2, 1, 1]), [2, 1, 1]);
+        assert_eq!(
+            map.inverse().map_corner([3, 4, 2], [2, 1, 1], [1, 2, 1]),
+            [1, 0, 1]
+        );
+    }
+}
@@ -0,0 +1,376 @@
+use crate::common::{Block, Dir};
+use crate::symmetry::PuzzleSymmetry;
+use crate::volume::{self, Puzzle, Retention, Solution, Symmetry};
+use itertools::Itertools;
+use log::debug;
+
+/// A packing of the blocks as blank shapes, with how many ways of turning the blocks about
+/// within their place meet the side targets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackingAssignments {
+    pub packing: Solution,
+    pub assignments: u128,
+    // the assignments which show different values on the sides: turning a block about
+    // without changing what it shows, e.g. a block with a single visible face, makes a twin
+    pub distinct: u128,
+    // by block id, the most ways of turning the block about within its place without changing
+    // what it shows, over the assignments; 1 for each block of a packing without any
+    pub free_turns: Vec<u64>,
+}
+
+/// Solve the puzzle with its face values in two phases: search the packings of the blocks as
+/// shapes once, then for each packing the orientations of the blocks which fill it and meet the
+/// side targets. Finds as many solutions as the search with values for the same symmetry;
+/// mirror images are told apart either way, as the face values do.
+pub fn solve(symmetry: Symmetry) -> Vec<PackingAssignments> {
+    let symmetry = match symmetry {
+        Symmetry::RotationsAndMirrors => Symmetry::Rotations,
+        symmetry => symmetry,
+    };
+    solve_puzzle(
+        &volume::puzzle(true, symmetry),
+        &volume::puzzle(false, symmetry),
+    )
+}
+
+// the packings of `shapes`, with the assignments of the orientations of `values` to them;
+// both puzzles have the same blocks, as shapes and with their values
+pub(crate) fn solve_puzzle(shapes: &Puzzle, values: &Puzzle) -> Vec<PackingAssignments> {
+    let dims = values.dims();
+    let assignments = Assignments {
+        dims,
+        target: values.target().expect("a puzzle with values has a target") as u16,
+        rot_blocks: values.rot_blocks(),
+        symmetries: PuzzleSymmetry::all(dims, values.rot_blocks(), true, values.symmetry()),
+    };
+    let mut solver = shapes.solver();
+    solver.set_retention(Retention::Nothing);
+    solver.init();
+    solver
+        .map(|packing| {
+            let (count, distinct, free_turns) = assignments.count(shapes.rot_blocks(), &packing);
+            debug!(
+                "packing {:?}: {} assignments, {} distinct",
+                packing.placements(),
+                count,
+                distinct
+            );
+            PackingAssignments {
+                packing,
+                assignments: count,
+                distinct,
+                free_turns,
+            }
+        })
+        .collect_vec()
+}
+
+// the orientations of the blocks in their place in a packing, as far as the sides can tell
+struct Place {
+    // which sides of the box the block touches
+    touched: [bool; 6],
+    // the rotations of the block which fill its place, grouped by the values they show on
+    // the sides of the box
+    groups: Vec<([u16; 6], Vec<usize>)>,
+}
+
+struct Assignments<'a> {
+    dims: [usize; 3],
+    target: u16,
+    // the blocks with their values, all their orientations
+    rot_blocks: &'a [Vec<Block>],
+    // the symmetries of the puzzle with values
+    symmetries: Vec<PuzzleSymmetry>,
+}
+
+impl Assignments<'_> {
+    // how many orientations of the blocks fill the packing of shapes and meet the targets,
+    // and how many of them show different values; counting those the symmetries of the packing
+    // turn into one another once. Along with the free turns of each block, by block id.
+    fn count(&self, shapes: &[Vec<Block>], packing: &Solution) -> (u128, u128, Vec<u64>) {
+        let [height, width, depth] = self.dims;
+        let places = packing
+            .placements()
+            .iter()
+            .map(|bip| {
+                let shape = &shapes[bip.block_id()][bip.rot_id()];
+                let shape_size = size(shape);
+                let position = bip.position();
+                let (x, y, z) = (
+                    position % height,
+                    position / height % width,
+                    position / (height * width),
+                );
+                let mut touched = [false; 6];
+                touched[Dir::Bottom as usize] = x == 0;
+                touched[Dir::Top as usize] = x + shape_size[0] == height;
+                touched[Dir::Left as usize] = y == 0;
+                touched[Dir::Right as usize] = y + shape_size[1] == width;
+                touched[Dir::Front as usize] = z == 0;
+                touched[Dir::Back as usize] = z + shape_size[2] == depth;
+                let mut groups: Vec<([u16; 6], Vec<usize>)> = vec![];
+                for (rot_id, rot) in self.rot_blocks[bip.block_id()].iter().enumerate() {
+                    if size(rot) != shape_size {
+                        continue;
+                    }
+                    let shown: [u16; 6] = std::array::from_fn(|side| {
+                        if touched[side] {
+                            rot.faces[side].value as u16
+                        } else {
+                            0
+                        }
+                    });
+                    match groups.iter_mut().find(|(values, _)| *values == shown) {
+                        Some((_, rot_ids)) => rot_ids.push(rot_id),
+                        None => groups.push((shown, vec![rot_id])),
+                    }
+                }
+                Place { touched, groups }
+            })
+            .collect_vec();
+        let mut free_turns = vec![1; self.rot_blocks.len()];
+        if places.iter().any(|place| place.groups.is_empty()) {
+            return (0, 0, free_turns);
+        }
+
+        // the most the places from each one on can still bring to each side
+        let mut most = vec![[0; 6]; places.len() + 1];
+        for (index, place) in places.iter().enumerate().rev() {
+            most[index] = std::array::from_fn(|side| {
+                most[index + 1][side]
+                    + place
+                        .groups
+                        .iter()
+                        .map(|(values, _)| values[side])
+                        .max()
+                        .unwrap_or(0)
+            });
+        }
+        debug_assert!((0..6).all(|side| places.iter().any(|place| place.touched[side])));
+
+        // the symmetries which map the packing of shapes onto itself, e.g. a half turn of a box
+        // filled with two layers; the assignments they map to one another count once
+        let stabilizer = self
+            .symmetries
+            .iter()
+            .filter(|symmetry| {
+                packing
+                    .placements()
+                    .iter()
+                    .zip(&places)
+                    .all(|(bip, place)| {
+                        let rot_id = place.groups[0].1[0];
+                        let rot = &self.rot_blocks[bip.block_id()][rot_id];
+                        let (mapped_rot, position) = symmetry.map(
+                            self.dims,
+                            size(rot),
+                            bip.block_id(),
+                            rot_id,
+                            bip.position(),
+                        );
+                        position == bip.position()
+                            && size(&self.rot_blocks[bip.block_id()][mapped_rot]) == size(rot)
+                    })
+            })
+            .collect_vec();
+        // by symmetry of the stabilizer and place, the group each group of orientations maps to
+        let group_maps = stabilizer
+            .iter()
+            .map(|symmetry| {
+                packing
+                    .placements()
+                    .iter()
+                    .zip(&places)
+                    .map(|(bip, place)| {
+                        place
+                            .groups
+                            .iter()
+                            .map(|(_, rot_ids)| {
+                                let rot = &self.rot_blocks[bip.block_id()][rot_ids[0]];
+                                let (mapped_rot, _) = symmetry.map(
+                                    self.dims,
+                                    size(rot),
+                                    bip.block_id(),
+                                    rot_ids[0],
+                                    bip.position(),
+                                );
+                                place
+                                    .groups
+                                    .iter()
+                                    .position(|(_, rot_ids)| rot_ids.contains(&mapped_rot))
+                                    .expect("a symmetry of the packing keeps the sizes")
+                            })
+                            .collect_vec()
+                    })
+                    .collect_vec()
+            })
+            .collect_vec();
+
+        let mut search = Search {
+            assignments: self,
+            packing,
+            places: &places,
+            most: &most,
+            stabilizer: &stabilizer,
+            group_maps: &group_maps,
+            chosen: vec![],
+            free_turns: vec![1; places.len()],
+        };
+        let (count, distinct) = search.count(0, [0; 6]);
+        for (bip, turns) in packing.placements().iter().zip(search.free_turns) {
+            free_turns[bip.block_id()] = turns;
+        }
+        (count, distinct, free_turns)
+    }
+}
+
+// a depth first search of the groups of orientations, place after place
+struct Search<'a> {
+    assignments: &'a Assignments<'a>,
+    packing: &'a Solution,
+    places: &'a [Place],
+    most: &'a [[u16; 6]],
+    stabilizer: &'a [&'a PuzzleSymmetry],
+    group_maps: &'a [Vec<Vec<usize>>],
+    // the group of orientations chosen for each place so far
+    chosen: Vec<usize>,
+    // by place, the largest group of orientations in the assignments counted so far
+    free_turns: Vec<u64>,
+}
+
+impl Search<'_> {
+    fn count(&mut self, index: usize, sums: [u16; 6]) -> (u128, u128) {
+        let target = self.assignments.target;
+        if (0..6).any(|side| sums[side] > target || sums[side] + self.most[index][side] < target) {
+            return (0, 0);
+        }
+        if index == self.places.len() {
+            return self.leaf();
+        }
+        let (mut count, mut distinct) = (0, 0);
+        for (group, (values, _)) in self.places[index].groups.iter().enumerate() {
+            self.chosen.push(group);
+            let (more, more_distinct) = self.count(
+                index + 1,
+                std::array::from_fn(|side| sums[side] + values[side]),
+            );
+            count += more;
+            distinct += more_distinct;
+            self.chosen.pop();
+        }
+        (count, distinct)
+    }
+
+    // the assignments from the chosen groups, which all meet the targets,
+    // and whether they count as distinct
+    fn leaf(&mut self) -> (u128, u128) {
+        let (count, distinct) = self.count_leaf();
+        if count > 0 {
+            for (turns, (&group, place)) in self
+                .free_turns
+                .iter_mut()
+                .zip(self.chosen.iter().zip(self.places))
+            {
+                *turns = (*turns).max(place.groups[group].1.len() as u64);
+            }
+        }
+        (count, distinct)
+    }
+
+    fn count_leaf(&self) -> (u128, u128) {
+        let rot_ids = self
+            .chosen
+            .iter()
+            .zip(self.places)
+            .map(|(&group, place)| &place.groups[group].1);
+        if self.stabilizer.is_empty() {
+            return (rot_ids.map(|rot_ids| rot_ids.len() as u128).product(), 1);
+        }
+        let first = self.group_maps.iter().all(|group_map| {
+            let image = self
+                .chosen
+                .iter()
+                .zip(group_map)
+                .map(|(&group, groups)| groups[group])
+                .collect_vec();
+            self.chosen <= image
+        });
+        // only count the assignments which come first among their images
+        let placements = self.packing.placements();
+        let sorted = |triples: &mut dyn Iterator<Item = (usize, usize, usize)>| {
+            triples.sorted().collect_vec()
+        };
+        let count = rot_ids
+            .multi_cartesian_product()
+            .filter(|rot_ids| {
+                let original = sorted(
+                    &mut placements
+                        .iter()
+                        .zip(rot_ids)
+                        .map(|(bip, &rot_id)| (bip.position(), bip.block_id(), *rot_id)),
+                );
+                self.stabilizer.iter().all(|symmetry| {
+                    let image =
+                        sorted(&mut placements.iter().zip(rot_ids).map(|(bip, &rot_id)| {
+                            let rot = &self.assignments.rot_blocks[bip.block_id()][*rot_id];
+                            let (rot_id, position) = symmetry.map(
+                                self.assignments.dims,
+                                size(rot),
+                                bip.block_id(),
+                                *rot_id,
+                                bip.position(),
+                            );
+                            (position, bip.block_id(), rot_id)
+                        }));
+                    original <= image
+                })
+            })
+            .count() as u128;
+        (count, first as u128)
+    }
+}
+
+fn size(block: &Block) -> [usize; 3] {
+    [
+        block.height as usize,
+        block.width as usize,
+        block.depth as usize,
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::volume::test::rot_blocks_2x_2x1x1_to_sum;
+
+    #[test]
+    fn two_phases_2x_2x1x1_in_2x2x1_to_sum() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let rot_blocks = rot_blocks_2x_2x1x1_to_sum();
+        for (symmetry, expected) in [(Symmetry::None, 8), (Symmetry::Rotations, 1)] {
+            let values = Puzzle::new(2, 2, 1, Some(12), rot_blocks.clone(), symmetry);
+            let mut solver = values.solver();
+            solver.init();
+            while solver.step() {}
+            assert_eq!(solver.solution_count(), expected);
+
+            let shape_rots = rot_blocks
+                .iter()
+                .map(|rots| {
+                    rots.iter()
+                        .unique_by(|rot| size(rot))
+                        .cloned()
+                        .collect_vec()
+                })
+                .collect_vec();
+            let shapes = Puzzle::new(2, 2, 1, None, shape_rots, symmetry);
+            let packings = solve_puzzle(&shapes, &values);
+            let total: u128 = packings.iter().map(|packing| packing.assignments).sum();
+            assert_eq!(total, expected);
+            assert!(packings
+                .iter()
+                .all(|packing| packing.distinct <= packing.assignments));
+        }
+    }
+}
@@ -1,55 +1,132 @@
-use crate::common::{Block, Dir, Face, BLOCK_COUNT, DEPTH, HEIGHT, WIDTH};
+use crate::bitset::BitSet;
+use crate::common::{Block, Dir, Face, DEPTH, HEIGHT, WIDTH};
+use crate::constraints::tile_region;
+use crate::dead_ends::DeadEnds;
+use crate::dlx::{Constraint, Dlx};
+use crate::observer::{Rejection, SearchObserver};
+use crate::placement::Placements;
+use crate::sink::SolutionSink;
+use crate::stats::SearchStats;
+use crate::symmetry::{BoxMap, PuzzleSymmetry};
 use itertools::Itertools;
 use lazy_static::lazy_static;
-use log::{debug, trace};
+use log::{debug, error, trace};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
 use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
 
+// the order the sides a block shows on are checked in, when placing it
+const SIDE_CHECK_ORDER: [Dir; 6] = [
+    Dir::Bottom,
+    Dir::Top,
+    Dir::Left,
+    Dir::Right,
+    Dir::Front,
+    Dir::Back,
+];
+
+/// A solver of the puzzle, counting the packings which are rotations of each other once
 pub fn solver(shape_only: bool) -> Solver {
+    solver_with_symmetry(shape_only, Symmetry::Rotations)
+}
+
+/// A solver of the puzzle, counting the packings symmetric to each other as asked
+pub fn solver_with_symmetry(shape_only: bool, symmetry: Symmetry) -> Solver {
+    solver_in_order(
+        shape_only,
+        symmetry,
+        CellOrder::default(),
+        BlockOrder::default(),
+    )
+}
+
+/// Same as `solver_with_symmetry`, the search filling the cells and trying the blocks
+/// in the given orders
+pub fn solver_in_order(
+    shape_only: bool,
+    symmetry: Symmetry,
+    order: CellOrder,
+    block_order: BlockOrder,
+) -> Solver {
+    let mut solver = puzzle_in_order(shape_only, symmetry, order, block_order).solver();
+    solver.init();
+    solver
+}
+
+/// A solver continuing the search saved by `Solver::checkpoint`, exactly where it stopped.
+/// Sinks and observers are not saved, and are to be registered again.
+pub fn resume<P: AsRef<Path>>(path: P) -> io::Result<Solver> {
+    let checkpoint: Checkpoint = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+    // only the shape-only search has no target
+    puzzle_in_order(
+        checkpoint.target.is_none(),
+        checkpoint.symmetry,
+        checkpoint.order,
+        checkpoint.block_order,
+    )
+    .restore(checkpoint)
+}
+
+pub(crate) fn puzzle(shape_only: bool, symmetry: Symmetry) -> Puzzle {
+    puzzle_in_order(
+        shape_only,
+        symmetry,
+        CellOrder::default(),
+        BlockOrder::default(),
+    )
+}
+
+pub(crate) fn puzzle_in_order(
+    shape_only: bool,
+    symmetry: Symmetry,
+    order: CellOrder,
+    block_order: BlockOrder,
+) -> Puzzle {
     let height = HEIGHT as usize;
     let width = WIDTH as usize;
     let depth = DEPTH as usize;
-    let volume = height * width * depth;
-    let (rot_blocks, target) = if shape_only {
-        (
-            crate::common::BLOCKS
-                .iter()
-                .map(all_shape_rots)
-                .collect_vec(),
-            None,
-        )
-    } else {
-        (
-            crate::common::BLOCKS
-                .iter()
-                .map(all_block_rots)
-                .collect_vec(),
-            Some(100),
-        )
-    };
-    let mut solver = Solver {
-        puzzle_height: height,
-        puzzle_width: width,
-        puzzle_depth: depth,
+    Puzzle::in_order(
+        [height, width, depth],
+        (!shape_only).then_some(100),
+        rot_blocks(&crate::common::BLOCKS, shape_only),
+        symmetry,
+        order,
+        block_order,
+    )
+}
+
+// the puzzle of the given blocks in a box of `dims`: as blank shapes without a target sum
+pub(crate) fn puzzle_of(
+    dims: [usize; 3],
+    blocks: &[Block],
+    target: Option<u8>,
+    symmetry: Symmetry,
+) -> Puzzle {
+    Puzzle::in_order(
+        dims,
         target,
-        rot_blocks,
-        stack: vec![],
-        rem: HashSet::from_iter(0..BLOCK_COUNT),
-        position: 0,
-        state: vec![None; volume],
-        face_sums: [0; 6],
-        face_free_areas: [
-            HEIGHT * WIDTH,
-            HEIGHT * WIDTH,
-            HEIGHT * DEPTH,
-            HEIGHT * DEPTH,
-            WIDTH * DEPTH,
-            WIDTH * DEPTH,
-        ],
-        done: false,
-        solutions: HashSet::new(),
-    };
-    solver.init();
-    solver
+        rot_blocks(blocks, target.is_none()),
+        symmetry,
+        CellOrder::default(),
+        BlockOrder::default(),
+    )
+}
+
+// the rotational variants of each block, as blank shapes or with their face values
+fn rot_blocks(blocks: &[Block], shape_only: bool) -> Vec<Vec<Block>> {
+    if shape_only {
+        blocks.iter().map(all_shape_rots).collect_vec()
+    } else {
+        blocks.iter().map(all_block_rots).collect_vec()
+    }
 }
 
 fn rot_face(axis: &Dir, face: &Face) -> Face {
@@ -247,7 +324,6 @@ fn rot_block(axis: &Dir, block: &Block) -> Block {
     }
 }
 
-// order matters! some optimisation relies on it, see `move_sideway_or_backtrack`
 lazy_static! {
     static ref BASE_ROTS: Vec<Vec<Dir>> = vec![
         vec![],
@@ -259,26 +335,6 @@ lazy_static! {
     ];
 }
 
-// create the rotational variants of a block, treating it as a faceless shape
-// usually 6 variants, possibly fewer:
-// 3 (square base case)
-// 1 (cube case)
-fn all_shape_rots(block: &Block) -> Vec<Block> {
-    let mut result = vec![];
-    for rots in &*BASE_ROTS {
-        let mut res = block.clone();
-        for rot in rots {
-            res = rot_block(rot, &res);
-        }
-        result.push(res);
-    }
-    result
-        // removes invariant rotations:
-        .into_iter()
-        .unique_by(|b| (b.height, b.width, b.depth))
-        .collect_vec()
-}
-
 lazy_static! {
     static ref FLIP_ROTS: Vec<Vec<Dir>> = vec![
         vec![],
@@ -289,7 +345,7 @@ lazy_static! {
 }
 
 // create the 24 rotational variants of a block
-fn all_block_rots(block: &Block) -> Vec<Block> {
+fn all_rots(block: &Block) -> Vec<Block> {
     let mut result = vec![];
     for rots_1 in &*BASE_ROTS {
         for rots_2 in &*FLIP_ROTS {
@@ -306,7 +362,41 @@ fn all_block_rots(block: &Block) -> Vec<Block> {
     result
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+// what the search tells rotations of a block apart by: the dimensions,
+// and the value on each side when values count
+fn looks(block: &Block, with_values: bool) -> (u8, u8, u8, Option<[u8; 6]>) {
+    (
+        block.height,
+        block.width,
+        block.depth,
+        with_values.then(|| block.faces.each_ref().map(|face| face.value)),
+    )
+}
+
+// the rotational variants of a block which look different to the search, in the order of
+// `all_rots`; each one stands for as many rotations as leave it looking the same
+fn distinct_rots(block: &Block, with_values: bool) -> Vec<Block> {
+    all_rots(block)
+        .into_iter()
+        .unique_by(|rot| looks(rot, with_values))
+        .collect_vec()
+}
+
+// create the rotational variants of a block, treating it as a faceless shape
+// usually 6 variants, possibly fewer:
+// 3 (square base case)
+// 1 (cube case)
+fn all_shape_rots(block: &Block) -> Vec<Block> {
+    distinct_rots(block, false)
+}
+
+// create the rotational variants of a block with its face values: up to 24,
+// fewer when the same values show on the same sides for several rotations
+fn all_block_rots(block: &Block) -> Vec<Block> {
+    distinct_rots(block, true)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct BlockInPuzzle {
     // the index of a block row within a reference [[Block]] 2D array
     block_id: usize,
@@ -316,716 +406,2303 @@ pub struct BlockInPuzzle {
     position: usize,
 }
 
-pub struct Solver {
-    puzzle_height: usize,
-    puzzle_width: usize,
-    puzzle_depth: usize,
+impl BlockInPuzzle {
+    pub fn block_id(&self) -> usize {
+        self.block_id
+    }
+
+    pub fn rot_id(&self) -> usize {
+        self.rot_id
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+/// A complete packing of the puzzle, as the list of placed blocks in placement order
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Solution {
+    placements: Vec<BlockInPuzzle>,
+}
+
+impl Solution {
+    pub fn placements(&self) -> &[BlockInPuzzle] {
+        &self.placements
+    }
+}
+
+/// The solutions a map of the box onto itself turns one another into, as one of them
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Orbit {
+    /// The canonical form of the solutions: the smallest of them, placements in cell order
+    pub solution: Solution,
+    /// How many distinct solutions there are
+    pub size: usize,
+}
+
+// what it takes to start a search from scratch; plain data, to be shared across threads
+#[derive(Debug, Clone)]
+pub(crate) struct Puzzle {
+    height: usize,
+    width: usize,
+    depth: usize,
     target: Option<u8>,
-    // for each base block, all its relevant rotations
     rot_blocks: Vec<Vec<Block>>,
-    stack: Vec<BlockInPuzzle>,
-    // ids of blocks still to be stacked
-    rem: HashSet<usize>,
-    // next position within state where to place a block
-    position: usize,
-    // 3d array tracking what space of the puzzle is filled with blocks
-    state: Vec<Option<BlockInPuzzle>>,
-    face_sums: [u8; 6],
-    face_free_areas: [u8; 6],
-    done: bool,
-    solutions: HashSet<Vec<BlockInPuzzle>>,
+    placements: Arc<Placements>,
+    // the puzzle as solutions give it, when the search goes in another order
+    frame: Option<Arc<Frame>>,
 }
 
-impl Solver {
-    pub fn block_count(&self) -> usize {
-        self.rot_blocks.len()
-    }
+// the box and blocks of a puzzle, when the search fills the cells or tries the blocks in another
+// order: the search sees the box mapped so that its first empty cell is the next in that order,
+// and the blocks numbered in the order they are tried in
+#[derive(Debug)]
+pub(crate) struct Frame {
+    order: CellOrder,
+    block_order: BlockOrder,
+    // from the box of the search back to the box of the solutions
+    map: BoxMap,
+    // by block id in the search, the id of the block in the solutions
+    block_ids: Vec<usize>,
+    dims: [usize; 3],
+    rot_blocks: Vec<Vec<Block>>,
+}
 
-    pub fn stack(&self) -> Vec<(&Block, usize, usize, usize, usize)> {
-        let slice_area = self.puzzle_height * self.puzzle_width;
-        self.stack
-            .iter()
-            .map(|bip| {
-                let x = bip.position % self.puzzle_height;
-                let y = (bip.position % slice_area) / self.puzzle_height;
-                let z = bip.position / slice_area;
-                (
-                    &self.rot_blocks[bip.block_id][bip.rot_id],
-                    bip.block_id,
-                    x,
-                    y,
-                    z,
-                )
-            })
-            .collect_vec()
+impl Puzzle {
+    #[cfg(test)]
+    pub(crate) fn new(
+        height: usize,
+        width: usize,
+        depth: usize,
+        target: Option<u8>,
+        rot_blocks: Vec<Vec<Block>>,
+        symmetry: Symmetry,
+    ) -> Puzzle {
+        Puzzle::in_order(
+            [height, width, depth],
+            target,
+            rot_blocks,
+            symmetry,
+            CellOrder::default(),
+            BlockOrder::default(),
+        )
     }
 
-    pub fn solutions(&self) -> Vec<String> {
-        self.solutions
+    pub(crate) fn in_order(
+        dims: [usize; 3],
+        target: Option<u8>,
+        rot_blocks: Vec<Vec<Block>>,
+        symmetry: Symmetry,
+        order: CellOrder,
+        block_order: BlockOrder,
+    ) -> Puzzle {
+        if order == CellOrder::default() && block_order == BlockOrder::default() {
+            let [height, width, depth] = dims;
+            return Puzzle::unmapped(height, width, depth, target, rot_blocks, symmetry);
+        }
+        let map = order.map();
+        let [mapped_height, mapped_width, mapped_depth] = map.map_dims(dims);
+        let block_ids = block_order.block_ids(dims, &rot_blocks);
+        let mapped_blocks = block_ids
             .iter()
-            .map(|sol| {
-                print_state(
-                    self.puzzle_height,
-                    self.puzzle_width,
-                    self.puzzle_depth,
-                    &self.rot_blocks,
-                    &sol.iter().map(|x| Some(*x)).collect_vec(),
-                )
+            .map(|&block_id| {
+                rot_blocks[block_id]
+                    .iter()
+                    .map(|block| map.map_block(block))
+                    .collect_vec()
             })
-            .collect_vec()
-    }
-
-    fn print_state(&self) -> String {
-        print_state(
-            self.puzzle_height,
-            self.puzzle_width,
-            self.puzzle_depth,
-            &self.rot_blocks,
-            &self.state,
-        )
+            .collect_vec();
+        let mut puzzle = Puzzle::unmapped(
+            mapped_height,
+            mapped_width,
+            mapped_depth,
+            target,
+            mapped_blocks,
+            symmetry,
+        );
+        puzzle.frame = Some(Arc::new(Frame {
+            order,
+            block_order,
+            map: map.inverse(),
+            block_ids,
+            dims,
+            rot_blocks,
+        }));
+        puzzle
     }
 
-    fn print_stack_tiny(&self) -> String {
-        let mut result = "[ ".to_string();
-        for bip in &self.stack {
-            result.push_str(&format!("({}, {}) ", bip.block_id, bip.rot_id));
+    fn unmapped(
+        height: usize,
+        width: usize,
+        depth: usize,
+        target: Option<u8>,
+        rot_blocks: Vec<Vec<Block>>,
+        symmetry: Symmetry,
+    ) -> Puzzle {
+        let placements = Arc::new(Placements::new(
+            height,
+            width,
+            depth,
+            target,
+            &rot_blocks,
+            symmetry,
+        ));
+        Puzzle {
+            height,
+            width,
+            depth,
+            target,
+            rot_blocks,
+            placements,
+            frame: None,
         }
-        result.push_str("] - sums: ");
-        result.push_str(&format!("{:?}", self.face_sums));
-        result
     }
 
-    pub fn done(&self) -> bool {
-        self.done
+    pub(crate) fn symmetry(&self) -> Symmetry {
+        self.placements.symmetry()
     }
 
-    pub fn step_to_solution(&mut self) -> bool {
-        while self.step() {
-            if self.stack.len() == self.block_count() {
-                return true;
-            }
-        }
-        false
+    pub(crate) fn order(&self) -> CellOrder {
+        self.frame
+            .as_ref()
+            .map_or(CellOrder::default(), |frame| frame.order)
     }
 
-    fn trace_deeper(&self) {
-        let bip = self.stack.last().unwrap();
-        trace!(
-            "deeper ({}={}), new block {} {}, rot {} - rem {:?}",
-            self.stack.len(),
-            self.print_stack_tiny(),
-            bip.block_id,
-            self.rot_blocks[bip.block_id][bip.rot_id].label,
-            bip.rot_id,
-            self.rem
-        );
+    pub(crate) fn block_order(&self) -> BlockOrder {
+        self.frame
+            .as_ref()
+            .map_or(BlockOrder::default(), |frame| frame.block_order)
     }
 
-    pub fn step(&mut self) -> bool {
-        trace!("step");
-        if !self.rem.is_empty() {
-            trace!("deeper?");
-            // case 1:
-            // try to go deeper (place a new block)
-            // note: sorting is not necessary, but convenient when tracing the execution
-            let rem = self.rem.clone().into_iter().sorted().collect_vec();
-            for block_id in rem {
-                for rot_id in 0..self.rot_blocks[block_id].len() {
-                    if self.deeper(block_id, rot_id) {
-                        self.trace_deeper();
-                        return true;
-                    }
-                }
-            }
-        }
+    pub(crate) fn dims(&self) -> [usize; 3] {
+        [self.height, self.width, self.depth]
+    }
 
-        trace!("not deeper, sideway?");
-        // cant place a new block ->
-        loop {
-            // case 2:
-            // move sideway in the graph (replace the block at the top of the stack)
-            // case 3:
-            // or backtrack
-            if self.move_sideway_or_backtrack() {
-                return true;
-            }
-            // case 4:
-            // cant move sideway, cant backtrack, the end
-            if self.stack.is_empty() {
-                break;
-            }
-        }
-        trace!("not sideway, done");
-        self.done = true;
-        false
+    pub(crate) fn target(&self) -> Option<u8> {
+        self.target
     }
 
-    fn move_sideway_or_backtrack(&mut self) -> bool {
-        // backtrack
-        let top = self.stack.pop().unwrap();
-        let block_id = top.block_id;
-        let rot_id = top.rot_id;
-        let position = top.position;
-        self.remove_block_from_state(top);
-        self.remove_block_from_face_state(top);
-        self.position = position;
-        self.rem.insert(block_id);
+    pub(crate) fn rot_blocks(&self) -> &[Vec<Block>] {
+        &self.rot_blocks
+    }
 
-        let mut limit = self.rot_blocks[block_id].len();
+    // a solver with no block placed yet
+    pub(crate) fn solver(&self) -> Solver {
+        let mut solver = Solver::with_placements(
+            self.height,
+            self.width,
+            self.depth,
+            self.target,
+            self.rot_blocks.clone(),
+            Arc::clone(&self.placements),
+        );
+        solver.frame = self.frame.clone();
+        solver
+    }
 
-        // discard rotational invariants:
-        // limit the possible rotations of the 1st block
-        if self.target.is_some() && self.stack.is_empty() {
-            limit /= 4; // ugly assumption: rotations must be conveniently ordered
-        };
+    // the stacks of all the nodes of the search tree at the given depth,
+    // plus the solutions found before reaching that depth, in search order
+    pub(crate) fn frontier(&self, depth: usize) -> Vec<Vec<BlockInPuzzle>> {
+        self.explore_frontier(depth, false).0
+    }
 
-        // try placing again the same block, with a different rot
-        for rot_id in rot_id + 1..limit {
-            if self.deeper(block_id, rot_id) {
-                trace!(
-                    "sideway a ({}={}), new block {}, rot {} - rem {:?}",
-                    self.stack.len(),
-                    self.print_stack_tiny(),
-                    block_id,
-                    rot_id,
-                    self.rem
-                );
-                return true;
-            } else {
-                trace!("skipped block {}, rot {} (doesnt fit)", block_id, rot_id);
-            }
+    // same as `frontier`, along with the finished search of the tree down to that depth,
+    // with stats if `stats`: the nodes at that depth are counted there, not in the subtrees
+    pub(crate) fn explore_frontier(
+        &self,
+        depth: usize,
+        stats: bool,
+    ) -> (Vec<Vec<BlockInPuzzle>>, Solver) {
+        let mut explorer = self.solver();
+        explorer.depth_limit = Some(depth);
+        explorer.retention = Retention::Nothing;
+        if stats {
+            explorer.enable_stats();
         }
-        // try placing a sibling
-        for block_id in block_id + 1..self.block_count() {
-            if !self.rem.contains(&block_id) {
-                continue;
-            }
-            for rot_id in 0..self.rot_blocks[block_id].len() {
-                if self.deeper(block_id, rot_id) {
-                    trace!(
-                        "sideway b ({}={}), new block {}, rot {} - rem {:?}",
-                        self.stack.len(),
-                        self.print_stack_tiny(),
-                        block_id,
-                        rot_id,
-                        self.rem
-                    );
-                    return true;
-                } else {
-                    trace!("skipped block {}, rot {} (doesnt fit)", block_id, rot_id);
-                }
+        let mut frontier = vec![];
+        if depth == 0 {
+            frontier.push(vec![]);
+        }
+        while explorer.step() {
+            if explorer.stack.len() == depth || explorer.solved() {
+                frontier.push(explorer.stack.clone());
             }
         }
-        // stay backtracked
-        trace!(
-            "not sideway, backtracking ({}={}) - rem {:?}",
-            self.stack.len(),
-            self.print_stack_tiny(),
-            self.rem
-        );
-        false
+        (frontier, explorer)
     }
 
-    fn remove_block_from_face_state(&mut self, bip: BlockInPuzzle) {
-        let block = &self.rot_blocks[bip.block_id][bip.rot_id];
-        let position = bip.position;
-
-        let slice_area = self.puzzle_height * self.puzzle_width;
+    // a solver restricted to the subtree below the given stack;
+    // none if the stack cannot be built by a search of this puzzle
+    pub(crate) fn subproblem(&self, prefix: &[BlockInPuzzle]) -> Option<Solver> {
+        self.solver().restricted_to(prefix)
+    }
 
-        let x_start = position % self.puzzle_height;
-        let x_end = x_start + block.height as usize;
+    // where the subtree below `stack` starts in the search order, as a fraction of the tree below
+    // the first `floor` blocks of the stack; see `Placements::fraction`
+    pub(crate) fn fraction(&self, stack: &[BlockInPuzzle], floor: usize) -> f64 {
+        let stack = packing(stack);
+        self.placements.fraction(&stack, floor)
+    }
 
-        let y_start = (position % slice_area) / self.puzzle_height;
-        let y_end = y_start + block.width as usize;
+    // a solver back in the state saved in the checkpoint
+    fn restore(&self, checkpoint: Checkpoint) -> io::Result<Solver> {
+        let rotations = self.rot_blocks.iter().map(Vec::len).collect_vec();
+        if (checkpoint.height, checkpoint.width, checkpoint.depth)
+            != (self.height, self.width, self.depth)
+            || checkpoint.target != self.target
+            || checkpoint.rotations != rotations
+            || checkpoint.symmetry != self.placements.symmetry()
+            || checkpoint.order != self.order()
+            || checkpoint.block_order != self.block_order()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "checkpoint of another puzzle",
+            ));
+        }
+        // placing the last block of a solution records it again, hence restoring the results after
+        let mut solver = self.subproblem(&checkpoint.stack).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "checkpoint stack does not fit")
+        })?;
+        solver.floor = checkpoint.floor;
+        solver.depth_limit = checkpoint.depth_limit;
+        solver.done = checkpoint.done;
+        solver.retention = checkpoint.retention;
+        solver.solutions = checkpoint.solutions;
+        solver.solution_count = checkpoint.solution_count;
+        solver.nodes = checkpoint.nodes;
+        solver.max_solutions = checkpoint.max_solutions;
+        solver.stats = checkpoint.stats;
+        solver.dead_space_pruning = checkpoint.dead_space_pruning;
+        solver.side_tiling_pruning = checkpoint.side_tiling_pruning;
+        solver.side_sum_pruning = checkpoint.side_sum_pruning;
+        solver.set_dead_end_cache(checkpoint.dead_end_cache);
+        // the subtrees of the stack were partly searched before the checkpoint,
+        // to unknown results: none of them is to be taken for a dead end
+        solver.completions_at.fill(u64::MAX);
+        Ok(solver)
+    }
+}
 
-        let z_start = position / slice_area;
-        let z_end = z_start + block.depth as usize;
+// the state of a search, as saved to a file; the rest of the solver follows from the stack
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    height: usize,
+    width: usize,
+    depth: usize,
+    target: Option<u8>,
+    // number of rotations of each block, to check the checkpoint matches the puzzle
+    rotations: Vec<usize>,
+    stack: Vec<BlockInPuzzle>,
+    floor: usize,
+    depth_limit: Option<usize>,
+    done: bool,
+    retention: Retention,
+    solutions: Vec<Solution>,
+    solution_count: u128,
+    nodes: u64,
+    max_solutions: Option<u128>,
+    stats: Option<SearchStats>,
+    dead_space_pruning: bool,
+    side_tiling_pruning: bool,
+    side_sum_pruning: bool,
+    symmetry: Symmetry,
+    order: CellOrder,
+    block_order: BlockOrder,
+    dead_end_cache: Option<usize>,
+}
 
-        let mut clean_face = |dir: Dir| {
-            let idx = dir as usize;
-            self.face_sums[idx] -= block.faces[idx].value;
-            self.face_free_areas[idx] += block.faces[idx].area();
-        };
+// the settings of a search, as inherited by searches of its subtrees
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Settings {
+    // whether the solutions are streamed to this search as soon as found
+    stream: bool,
+    retention: Retention,
+    max_solutions: Option<u128>,
+    stats: bool,
+    dead_space_pruning: bool,
+    side_tiling_pruning: bool,
+    side_sum_pruning: bool,
+    dead_end_cache: Option<usize>,
+}
 
-        if self.target.is_some() {
-            if x_start == 0 {
-                clean_face(Dir::Bottom);
-            }
-            if x_end == self.puzzle_height {
-                clean_face(Dir::Top);
-            }
-            if y_start == 0 {
-                clean_face(Dir::Left);
-            }
-            if y_end == self.puzzle_width {
-                clean_face(Dir::Right);
-            }
-            if z_start == 0 {
-                clean_face(Dir::Front);
-            }
-            if z_end == self.puzzle_depth {
-                clean_face(Dir::Back);
-            }
-        }
+impl Settings {
+    // whether the searches of subtrees hand over their solutions as soon as found
+    pub(crate) fn streams(&self) -> bool {
+        self.stream
     }
+}
 
-    fn remove_block_from_state(&mut self, bip: BlockInPuzzle) {
-        let position = bip.position;
-        let block = &self.rot_blocks[bip.block_id][bip.rot_id];
+/// Which maps of the box onto itself a search counts the packings of as one: of the packings
+/// mapped to each other, only one is searched. Mirror images only count as symmetric when every
+/// block looks the same in a mirror, as in the shape-only search.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Symmetry {
+    // every packing is searched
+    #[default]
+    None,
+    Rotations,
+    RotationsAndMirrors,
+}
 
-        let slice_area = self.puzzle_height * self.puzzle_width;
+/// The order the backtracking search fills the cells of the box in: the first empty cell along
+/// `axes`, from the fastest changing to the slowest (0: height, 1: width, 2: depth), each axis
+/// from its far end when `reversed`. The search finds as many solutions in any order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CellOrder {
+    pub axes: [usize; 3],
+    // by axis of the box
+    pub reversed: [bool; 3],
+}
 
-        let x_start = position % self.puzzle_height;
-        let x_end = x_start + block.height as usize;
+impl Default for CellOrder {
+    fn default() -> Self {
+        CellOrder {
+            axes: [0, 1, 2],
+            reversed: [false; 3],
+        }
+    }
+}
 
-        let y_start = (position % slice_area) / self.puzzle_height;
-        let y_end = y_start + block.width as usize;
+impl CellOrder {
+    // the map of the box in which the order is the default one
+    fn map(&self) -> BoxMap {
+        assert!(
+            self.axes.iter().sorted().eq(&[0, 1, 2]),
+            "cell order axes must be 0, 1 and 2 in some order"
+        );
+        BoxMap::new(self.axes, self.axes.map(|axis| self.reversed[axis]))
+    }
+}
 
-        let z_start = position / slice_area;
-        let z_end = z_start + block.depth as usize;
+/// The order the search tries the blocks in, at each cell; as many solutions are found in any order,
+/// the first ones differ. Observers and `Solver::stack` see the blocks numbered in that order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlockOrder {
+    // by id, as the puzzle lists them
+    #[default]
+    Id,
+    // the blocks with the largest volume first
+    LargestVolume,
+    // the blocks with the most faces bringing a value to the sides first, then the largest values
+    MostSideFaces,
+    // the blocks with the fewest placements in the empty box first
+    FewestPlacements,
+    // shuffled, from the given seed
+    Random(u64),
+}
 
-        for k in z_start..z_end {
-            for j in y_start..y_end {
-                for i in x_start..x_end {
-                    let idx = k * slice_area + j * self.puzzle_height + i;
-                    self.state[idx] = None;
+impl BlockOrder {
+    // by rank, the ids of the blocks of a puzzle, ties by id
+    fn block_ids(&self, dims: [usize; 3], rot_blocks: &[Vec<Block>]) -> Vec<usize> {
+        let mut block_ids = (0..rot_blocks.len()).collect_vec();
+        let base = |block_id: usize| &rot_blocks[block_id][0];
+        match *self {
+            BlockOrder::Id => {}
+            BlockOrder::LargestVolume => block_ids.sort_by_key(|&block_id| {
+                let block = base(block_id);
+                Reverse(block.height as usize * block.width as usize * block.depth as usize)
+            }),
+            BlockOrder::MostSideFaces => block_ids.sort_by_key(|&block_id| {
+                let faces = &base(block_id).faces;
+                Reverse((
+                    faces.iter().filter(|face| face.value > 0).count(),
+                    faces.iter().map(|face| face.value as usize).sum::<usize>(),
+                ))
+            }),
+            BlockOrder::FewestPlacements => block_ids.sort_by_key(|&block_id| {
+                rot_blocks[block_id]
+                    .iter()
+                    .map(|block| {
+                        [block.height, block.width, block.depth]
+                            .iter()
+                            .zip(dims)
+                            .map(|(&size, dim)| (dim + 1).saturating_sub(size as usize))
+                            .product::<usize>()
+                    })
+                    .sum::<usize>()
+            }),
+            BlockOrder::Random(seed) => {
+                let mut rng = Rng::new(seed);
+                for i in (1..block_ids.len()).rev() {
+                    block_ids.swap(i, rng.below(i + 1));
                 }
             }
         }
+        block_ids
     }
+}
 
-    // try to go deeper in the solution graph, by placing one more rotated block
-    fn deeper(&mut self, block_id: usize, rot_id: usize) -> bool {
-        match self.place_3d(BlockInPuzzle {
-            block_id,
-            rot_id,
-            position: self.position,
-        }) {
-            Some(new_position) => {
-                self.stack.push(BlockInPuzzle {
-                    block_id,
-                    rot_id,
-                    position: self.position,
-                });
-                self.rem.remove(&block_id);
-                self.position = new_position;
-                if self.position == self.puzzle_height * self.puzzle_width * self.puzzle_depth {
-                    let solution = self.print_state();
-                    debug!("solution:\n{}\n{}", &solution, self.print_stack_tiny());
-                    self.solutions
-                        .insert(self.state.iter().map(|mbip| mbip.unwrap()).collect_vec());
-                }
-                true
-            }
-            None => false,
+/// The search algorithms of `Solver::run`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    // places blocks at the first free cell, depth first, as `step` does
+    Backtracking,
+    // Algorithm X over all the placements, covering each cell and using each block once
+    ExactCover,
+    // places blocks at the empty cell the fewest placements fit, among the corners of the
+    // empty space, depth first
+    MostConstrained,
+}
+
+/// What a solver keeps of the solutions it finds; all of them are counted regardless
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Retention {
+    All,
+    // only the first solutions found, up to the given amount
+    Sample(usize),
+    Nothing,
+}
+
+/// How a bounded run of the search ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    // the search is over
+    Finished,
+    // the cancellation token was triggered
+    Cancelled,
+    // the budget ran out, the search can go on
+    Paused,
+    // stopped on a solution, when stepping to solutions
+    Solved,
+}
+
+/// How far a search has got
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    // explored fraction of the search tree, assuming all the branches of a node
+    // are of the same size
+    pub fraction: f64,
+    // nodes of the search tree explored so far
+    pub nodes: u64,
+}
+
+impl Progress {
+    /// The time left to the end of the search, at the pace of the `elapsed` time so far
+    pub fn remaining(&self, elapsed: Duration) -> Option<Duration> {
+        (self.fraction > 0.).then(|| elapsed.mul_f64((1. - self.fraction) / self.fraction))
+    }
+
+    /// The same progress, measured by the nodes explored out of an `estimate` of the nodes of
+    /// the whole tree (see `Solver::estimate_nodes`) rather than by the branches taken.
+    /// Short of 1 until the search is over, however far off the estimate.
+    pub fn of_estimate(&self, estimate: f64) -> Progress {
+        Progress {
+            fraction: if self.fraction < 1. {
+                (self.nodes as f64 / estimate).min(0.99)
+            } else {
+                1.
+            },
+            nodes: self.nodes,
         }
     }
+}
 
-    fn init(&mut self) -> bool {
-        trace!("init");
-        self.deeper(0, 0);
-        trace!(
-            "deeper ({}={}), new block {}, rot {} - rem {:?}",
-            self.stack.len(),
-            self.print_stack_tiny(),
-            0,
-            0,
-            self.rem
-        );
-        true
+/// Stops the bounded runs of a search, from any thread; see `Solver::cancel_token`
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
     }
 
-    fn place_3d(&mut self, bip: BlockInPuzzle) -> Option<usize> {
-        let block = &self.rot_blocks[bip.block_id][bip.rot_id];
-        let slice_area = self.puzzle_height * self.puzzle_width;
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
 
-        let start_point = bip.position;
+pub struct Solver {
+    puzzle_height: usize,
+    puzzle_width: usize,
+    puzzle_depth: usize,
+    target: Option<u8>,
+    // for each base block, all its relevant rotations
+    rot_blocks: Vec<Vec<Block>>,
+    stack: Vec<BlockInPuzzle>,
+    // ids of blocks still to be stacked
+    rem: HashSet<usize>,
+    // next position within state where to place a block
+    position: usize,
+    // what space of the puzzle is filled with blocks, one bit per cell
+    occupied: BitSet,
+    // shared by all the solvers of the puzzle
+    placements: Arc<Placements>,
+    face_sums: [u8; 6],
+    face_free_areas: [u8; 6],
+    done: bool,
+    retention: Retention,
+    solutions: Vec<Solution>,
+    solution_count: u128,
+    // blocks placed so far, i.e. nodes of the search tree explored
+    nodes: u64,
+    // stop the search once that many solutions are found
+    max_solutions: Option<u128>,
+    // notified of every solution found
+    sinks: Vec<Box<dyn SolutionSink + Send>>,
+    observers: Vec<Box<dyn SearchObserver + Send>>,
+    // collected only once enabled
+    stats: Option<SearchStats>,
+    // the search never backtracks past that many blocks in the stack
+    floor: usize,
+    // the search never places more than that many blocks
+    depth_limit: Option<usize>,
+    // cut the placements which wall off a region the remaining blocks cannot fill
+    dead_space_pruning: bool,
+    // cut the placements which leave a side the remaining block faces cannot tile
+    side_tiling_pruning: bool,
+    // cut the placements which leave a side sum out of reach of the remaining block faces
+    side_sum_pruning: bool,
+    // the states searched to no solution, if remembered
+    dead_ends: Option<DeadEnds>,
+    // by stack level, the Zobrist key of the cells taken and the blocks placed so far
+    keys: Vec<u64>,
+    // packings completed so far, solutions or not
+    completions: u64,
+    // by stack level, the packings completed before the block was placed
+    completions_at: Vec<u64>,
+    // the puzzle as solutions give it, when the search goes in another order
+    frame: Option<Arc<Frame>>,
+    cancel: CancelToken,
+}
 
-        let x_start = start_point % self.puzzle_height;
-        let x_end = x_start + block.height as usize;
+impl Solver {
+    // a solver of its own puzzle; `Puzzle::solver` shares the placement tables instead
+    #[cfg(test)]
+    pub(crate) fn new(
+        puzzle_height: usize,
+        puzzle_width: usize,
+        puzzle_depth: usize,
+        target: Option<u8>,
+        rot_blocks: Vec<Vec<Block>>,
+    ) -> Solver {
+        let placements = Placements::new(
+            puzzle_height,
+            puzzle_width,
+            puzzle_depth,
+            target,
+            &rot_blocks,
+            Symmetry::None,
+        );
+        Solver::with_placements(
+            puzzle_height,
+            puzzle_width,
+            puzzle_depth,
+            target,
+            rot_blocks,
+            Arc::new(placements),
+        )
+    }
 
-        let y_start = (start_point % slice_area) / self.puzzle_height;
-        let y_end = y_start + block.width as usize;
+    fn with_placements(
+        puzzle_height: usize,
+        puzzle_width: usize,
+        puzzle_depth: usize,
+        target: Option<u8>,
+        rot_blocks: Vec<Vec<Block>>,
+        placements: Arc<Placements>,
+    ) -> Solver {
+        let block_count = rot_blocks.len();
+        let (h, w, d) = (puzzle_height as u8, puzzle_width as u8, puzzle_depth as u8);
+        Solver {
+            puzzle_height,
+            puzzle_width,
+            puzzle_depth,
+            target,
+            rot_blocks,
+            stack: vec![],
+            rem: HashSet::from_iter(0..block_count),
+            position: 0,
+            occupied: BitSet::new(puzzle_height * puzzle_width * puzzle_depth),
+            placements,
+            face_sums: [0; 6],
+            face_free_areas: [h * w, h * w, h * d, h * d, w * d, w * d],
+            done: false,
+            retention: Retention::All,
+            solutions: vec![],
+            solution_count: 0,
+            nodes: 0,
+            max_solutions: None,
+            sinks: vec![],
+            observers: vec![],
+            stats: None,
+            floor: 0,
+            depth_limit: None,
+            dead_space_pruning: false,
+            side_tiling_pruning: false,
+            side_sum_pruning: true,
+            dead_ends: None,
+            keys: vec![],
+            completions: 0,
+            completions_at: vec![],
+            frame: None,
+            cancel: CancelToken::default(),
+        }
+    }
 
-        let z_start = start_point / slice_area;
-        let z_end = z_start + block.depth as usize;
+    pub(crate) fn puzzle(&self) -> Puzzle {
+        Puzzle {
+            height: self.puzzle_height,
+            width: self.puzzle_width,
+            depth: self.puzzle_depth,
+            target: self.target,
+            rot_blocks: self.rot_blocks.clone(),
+            placements: Arc::clone(&self.placements),
+            frame: self.frame.clone(),
+        }
+    }
 
-        if x_end > self.puzzle_height || y_end > self.puzzle_width || z_end > self.puzzle_depth {
-            trace!("block sticks out");
-            return None;
+    fn order(&self) -> CellOrder {
+        self.frame
+            .as_ref()
+            .map_or(CellOrder::default(), |frame| frame.order)
+    }
+
+    fn block_order(&self) -> BlockOrder {
+        self.frame
+            .as_ref()
+            .map_or(BlockOrder::default(), |frame| frame.block_order)
+    }
+
+    // the dimensions and blocks of the puzzle, as solutions give them
+    fn solution_frame(&self) -> ([usize; 3], &[Vec<Block>]) {
+        match &self.frame {
+            Some(frame) => (frame.dims, &frame.rot_blocks),
+            None => (
+                [self.puzzle_height, self.puzzle_width, self.puzzle_depth],
+                &self.rot_blocks,
+            ),
         }
-        if let Some(target_sum) = self.target {
-            // if a puzzle face is completed, its value must add up to target_sum
-            if x_start == 0 {
-                let idx = Dir::Bottom as usize;
-                let current_sum = self.face_sums[idx];
-                let new_sum = current_sum + block.faces[idx].value;
-                if new_sum > target_sum {
-                    trace!("bottom sum too big");
-                    return None;
-                }
-                if new_sum == target_sum {
-                    for k in 0..self.puzzle_depth {
-                        for j in 0..self.puzzle_width {
-                            let idx = k * slice_area + j * self.puzzle_height;
-                            // where the block would fit on the face
-                            if k >= z_start && k < z_end && j >= y_start && j < y_end {
-                                if self.state[idx].is_some() {
-                                    // if there's another block -> bail
-                                    trace!("bottom there's a block here");
-                                    return None;
-                                } else {
-                                    // if it's empty -> ok
-                                    continue;
-                                }
-                            }
-                            // this is spot is not filled nor would be filled by the block -> bail
-                            if self.state[idx].is_none() {
-                                trace!("bottom sum==target_sum but face not full");
-                                return None;
-                            }
-                        }
-                    }
-                }
-                if new_sum < target_sum
-                    && self.face_free_areas[idx] as i16 - block.faces[idx].area() as i16 == 0
-                {
-                    return None;
-                }
-            }
-            if x_end == self.puzzle_height {
-                let idx = Dir::Top as usize;
-                let current_sum = self.face_sums[idx];
-                let new_sum = current_sum + block.faces[idx].value;
-                if new_sum > target_sum {
-                    trace!("top sum too big");
-                    return None;
-                }
-                if new_sum == target_sum {
-                    for k in 0..self.puzzle_depth {
-                        for j in 0..self.puzzle_width {
-                            let idx =
-                                k * slice_area + j * self.puzzle_height + self.puzzle_height - 1;
-                            // where the block would fit on the face
-                            if k >= z_start && k < z_end && j >= y_start && j < y_end {
-                                if self.state[idx].is_some() {
-                                    // if there's another block -> bail
-                                    trace!("top there's a block here");
-                                    return None;
-                                } else {
-                                    // if it's empty -> ok
-                                    continue;
-                                }
-                            }
-                            // this is spot is not filled nor would be filled by the block -> bail
-                            if self.state[idx].is_none() {
-                                trace!("front sum==target_sum but face not full");
-                                return None;
-                            }
-                        }
-                    }
-                }
-                if new_sum < target_sum
-                    && self.face_free_areas[idx] as i16 - block.faces[idx].area() as i16 == 0
-                {
-                    return None;
-                }
-            }
+    }
 
-            if y_start == 0 {
-                let idx = Dir::Left as usize;
-                let current_sum = self.face_sums[idx];
-                let new_sum = current_sum + block.faces[idx].value;
-                if new_sum > target_sum {
-                    trace!("left sum too big");
-                    return None;
-                }
-                if new_sum == target_sum {
-                    for k in 0..self.puzzle_depth {
-                        for i in 0..self.puzzle_height {
-                            let idx = k * slice_area + i;
-                            // where the block would fit on the face
-                            if k >= z_start && k < z_end && i >= x_start && i < x_end {
-                                if self.state[idx].is_some() {
-                                    // if there's another block -> bail
-                                    trace!("left there's a block here");
-                                    return None;
-                                } else {
-                                    // if it's empty -> ok
-                                    continue;
-                                }
-                            }
-                            // this is spot is not filled nor would be filled by the block -> bail
-                            if self.state[idx].is_none() {
-                                trace!("left sum==target_sum but face not full");
-                                return None;
-                            }
-                        }
-                    }
-                }
-                if new_sum < target_sum
-                    && self.face_free_areas[idx] as i16 - block.faces[idx].area() as i16 == 0
-                {
-                    return None;
-                }
-            }
-            if y_end == self.puzzle_width {
-                let idx = Dir::Right as usize;
-                let current_sum = self.face_sums[idx];
-                let new_sum = current_sum + block.faces[idx].value;
-                if new_sum > target_sum {
-                    trace!("right sum too big");
-                    return None;
-                }
-                if new_sum == target_sum {
-                    for k in 0..self.puzzle_depth {
-                        for i in 0..self.puzzle_height {
-                            let idx =
-                                k * slice_area + (self.puzzle_width - 1) * self.puzzle_height + i;
-                            // where the block would fit on the face
-                            if k >= z_start && k < z_end && i >= x_start && i < x_end {
-                                if self.state[idx].is_some() {
-                                    // if there's another block -> bail
-                                    trace!("right there's a block here");
-                                    return None;
-                                } else {
-                                    // if it's empty -> ok
-                                    continue;
-                                }
-                            }
-                            // this is spot is not filled nor would be filled by the block -> bail
-                            if self.state[idx].is_none() {
-                                trace!("right sum==target_sum but face not full");
-                                return None;
-                            }
-                        }
-                    }
-                }
-                if new_sum < target_sum
-                    && self.face_free_areas[idx] as i16 - block.faces[idx].area() as i16 == 0
-                {
-                    return None;
-                }
+    // the solution made of blocks placed in the box of the search, in placement order
+    // as the backtracking search in the default order would have them
+    fn solution_of(&self, mut placements: Vec<BlockInPuzzle>) -> Solution {
+        if let Some(frame) = &self.frame {
+            let dims = [self.puzzle_height, self.puzzle_width, self.puzzle_depth];
+            let [height, width, _] = frame.dims;
+            for bip in &mut placements {
+                let block = &self.rot_blocks[bip.block_id][bip.rot_id];
+                let (x, y, z) = self.coords(bip.position);
+                let [x, y, z] = frame.map.map_corner(
+                    dims,
+                    [x, y, z],
+                    [
+                        block.height as usize,
+                        block.width as usize,
+                        block.depth as usize,
+                    ],
+                );
+                bip.position = (z * width + y) * height + x;
+                bip.block_id = frame.block_ids[bip.block_id];
             }
+        }
+        placements.sort_by_key(|bip| bip.position);
+        Solution { placements }
+    }
 
-            if z_start == 0 {
-                let idx = Dir::Front as usize;
-                let current_sum = self.face_sums[idx];
-                let new_sum = current_sum + block.faces[idx].value;
-                if new_sum > target_sum {
-                    trace!("front sum too big");
-                    return None;
-                }
-                if new_sum == target_sum {
-                    for j in 0..self.puzzle_width {
-                        for i in 0..self.puzzle_height {
-                            let idx = j * self.puzzle_height + i;
-                            // where the block would fit on the face
-                            if j >= y_start && j < y_end && i >= x_start && i < x_end {
-                                if self.state[idx].is_some() {
-                                    // if there's another block -> bail
-                                    trace!("front there's a block here");
-                                    return None;
-                                } else {
-                                    // if it's empty -> ok
-                                    continue;
-                                }
-                            }
-                            // this is spot is not filled nor would be filled by the block -> bail
-                            if self.state[idx].is_none() {
-                                trace!("front sum==target_sum but face not full");
-                                return None;
-                            }
-                        }
-                    }
-                }
-                if new_sum < target_sum
-                    && self.face_free_areas[idx] as i16 - block.faces[idx].area() as i16 == 0
-                {
-                    return None;
-                }
-            }
-            if z_end == self.puzzle_depth {
-                let idx = Dir::Back as usize;
-                let current_sum = self.face_sums[idx];
-                let new_sum = current_sum + block.faces[idx].value;
-                if new_sum > target_sum {
-                    trace!("back sum too big");
-                    return None;
-                }
-                if new_sum == target_sum {
-                    for j in 0..self.puzzle_width {
-                        for i in 0..self.puzzle_height {
-                            let idx =
-                                (self.puzzle_depth - 1) * slice_area + j * self.puzzle_height + i;
-                            // where the block would fit on the face
-                            if j >= y_start && j < y_end && i >= x_start && i < x_end {
-                                if self.state[idx].is_some() {
-                                    // if there's another block -> bail
-                                    trace!("back there's a block here");
-                                    return None;
-                                } else {
-                                    // if it's empty -> ok
-                                    continue;
-                                }
-                            }
-                            // this is spot is not filled nor would be filled by the block -> bail
-                            if self.state[idx].is_none() {
-                                trace!("back sum==target_sum but face not full");
-                                return None;
-                            }
-                        }
-                    }
-                }
-                if new_sum < target_sum
-                    && self.face_free_areas[idx] as i16 - block.faces[idx].area() as i16 == 0
-                {
-                    return None;
-                }
+    // Restrict this solver, fresh from its puzzle, to the subtree below the given stack;
+    // none if the stack cannot be built by its search. Its sinks and observers are handed
+    // the stack if a solution already.
+    pub(crate) fn restricted_to(mut self, prefix: &[BlockInPuzzle]) -> Option<Solver> {
+        for bip in prefix {
+            if bip.block_id >= self.block_count()
+                || bip.rot_id >= self.rot_blocks[bip.block_id].len()
+                || !self.rem.contains(&bip.block_id)
+                || bip.position != self.position
+                || !self.deeper(bip.block_id, bip.rot_id)
+            {
+                return None;
             }
         }
+        self.floor = prefix.len();
+        Some(self)
+    }
 
-        // only if the block fits...
-        for k in z_start..z_end {
-            for j in y_start..y_end {
-                for i in x_start..x_end {
-                    let idx = k * slice_area + j * self.puzzle_height + i;
-                    if self.state[idx].is_some() {
-                        trace!("there's a block here");
-                        return None;
-                    }
-                }
-            }
+    pub(crate) fn settings(&self) -> Settings {
+        // sinks and observers are handed the solutions of subtrees as they are found,
+        // rather than all at once when the subtree is taken in
+        let stream = !self.sinks.is_empty() || !self.observers.is_empty();
+        Settings {
+            stream,
+            retention: if stream {
+                Retention::Nothing
+            } else {
+                self.retention
+            },
+            max_solutions: self.max_solutions,
+            stats: self.stats.is_some(),
+            dead_space_pruning: self.dead_space_pruning,
+            side_tiling_pruning: self.side_tiling_pruning,
+            side_sum_pruning: self.side_sum_pruning,
+            dead_end_cache: self.dead_ends.as_ref().map(DeadEnds::capacity),
         }
-        // ... is the state updated
-        for k in z_start..z_end {
-            for j in y_start..y_end {
-                for i in x_start..x_end {
-                    let idx = k * slice_area + j * self.puzzle_height + i;
-                    self.state[idx] = Some(bip);
-                }
-            }
+    }
+
+    pub(crate) fn apply(&mut self, settings: Settings) {
+        self.retention = settings.retention;
+        self.max_solutions = settings.max_solutions;
+        self.dead_space_pruning = settings.dead_space_pruning;
+        self.side_tiling_pruning = settings.side_tiling_pruning;
+        self.side_sum_pruning = settings.side_sum_pruning;
+        self.set_dead_end_cache(settings.dead_end_cache);
+        if settings.stats {
+            self.enable_stats();
         }
+    }
 
-        if self.target.is_some() {
-            let mut add_block_to_face = |dir: Dir| {
-                let idx = dir as usize;
-                self.face_sums[idx] += block.faces[idx].value;
-                self.face_free_areas[idx] -= block.faces[idx].area();
-            };
-            if x_start == 0 {
-                add_block_to_face(Dir::Bottom);
-            }
-            if x_end == self.puzzle_height {
-                add_block_to_face(Dir::Top);
-            }
-            if y_start == 0 {
-                add_block_to_face(Dir::Left);
-            }
-            if y_end == self.puzzle_width {
-                add_block_to_face(Dir::Right);
-            }
-            if z_start == 0 {
-                add_block_to_face(Dir::Front);
-            }
-            if z_end == self.puzzle_depth {
-                add_block_to_face(Dir::Back);
-            }
+    // Start the counts of the search over, stats included, for a search from scratch
+    pub(crate) fn forget_nodes(&mut self) {
+        self.nodes = 0;
+        if self.stats.is_some() {
+            self.enable_stats();
         }
+    }
 
-        let volume = slice_area * self.puzzle_depth;
-        let new_start_point = self
-            .state
-            .iter()
-            .skip(start_point)
-            .position(|&e| e.is_none())
-            .map(|r| r + start_point)
-            .unwrap_or(volume);
-        Some(new_start_point)
+    // Take in the results of a search of a subtree, as if found by this solver, but for the
+    // first `taken` of its solutions, already taken in one by one
+    pub(crate) fn absorb(&mut self, other: Solver, taken: u128) {
+        let mut count = other.solution_count - taken;
+        if let Some(max) = self.max_solutions {
+            count = count.min(max.saturating_sub(self.solution_count));
+        }
+        // the other solver kept the first of its solutions, if any
+        let kept = count.min(other.solutions.len() as u128);
+        // the stats of the other search already account for its solutions
+        let mut stats = self.stats.take();
+        for solution in other.solutions.into_iter().take(kept as usize) {
+            self.accept_solution(solution);
+        }
+        self.solution_count += count - kept;
+        // the blocks below the floor were placed by the search of the tree above the subtree
+        self.nodes += other.nodes - other.floor as u64;
+        if let (Some(stats), Some(other_stats)) = (&mut stats, &other.stats) {
+            stats.merge(other_stats);
+        }
+        self.stats = stats;
     }
-}
 
-fn print_state(
-    puzzle_height: usize,
-    puzzle_width: usize,
-    puzzle_depth: usize,
-    rot_blocks: &[Vec<Block>],
-    state: &[Option<BlockInPuzzle>],
-) -> String {
-    let slice_area = puzzle_height * puzzle_width;
+    // Take in a solution found by the search of a subtree, as if found by this solver,
+    // unless the solution limit is reached
+    pub(crate) fn absorb_solution(&mut self, solution: Solution) {
+        if self.limit_reached() {
+            return;
+        }
+        // the stats of the other search already account for the solution
+        let stats = self.stats.take();
+        self.accept_solution(solution);
+        self.stats = stats;
+    }
 
-    let mut result = String::new();
+    pub(crate) fn limit_reached(&self) -> bool {
+        self.max_solutions
+            .is_some_and(|max| self.solution_count >= max)
+    }
 
-    result.push_str("Front:\n");
-    for i in (0..puzzle_height).rev() {
-        for j in 0..puzzle_width {
-            let idx = j * puzzle_height + i;
-            result.push_str(&format!(
-                "{} ",
-                state[idx]
-                    .map(|b| format!(
-                        "{:0>2}",
-                        rot_blocks[b.block_id][b.rot_id].faces[Dir::Front as usize].value
-                    ))
-                    .unwrap_or("".to_string())
-            ));
+    // end the search, with an empty stack
+    pub(crate) fn finish(&mut self) {
+        while !self.stack.is_empty() {
+            self.pop_block();
         }
-        result.push('\n');
+        self.done = true;
     }
 
-    result.push_str("Back:\n");
-    for i in (0..puzzle_height).rev() {
-        for j in (0..puzzle_width).rev() {
-            let idx = (puzzle_depth - 1) * slice_area + j * puzzle_height + i;
-            result.push_str(&format!(
-                "{} ",
-                state[idx]
-                    .map(|b| format!(
-                        "{:0>2}",
-                        rot_blocks[b.block_id][b.rot_id].faces[Dir::Back as usize].value
-                    ))
-                    .unwrap_or("".to_string())
-            ));
+    // take the block at the top of the stack off the puzzle
+    fn pop_block(&mut self) -> BlockInPuzzle {
+        let top = self.stack.pop().unwrap();
+        self.keys.pop();
+        self.completions_at.pop();
+        self.remove_block_from_state(top);
+        self.remove_block_from_face_state(top);
+        self.position = top.position;
+        self.rem.insert(top.block_id);
+        top
+    }
+
+    /// Where the search stands, from the rank of each block of the stack among its siblings
+    pub fn progress(&self) -> Progress {
+        Progress {
+            fraction: if self.done {
+                1.
+            } else {
+                self.placements.fraction(&packing(&self.stack), self.floor)
+            },
+            nodes: self.nodes,
         }
-        result.push('\n');
     }
 
-    result.push_str("Left:\n");
-    for i in (0..puzzle_height).rev() {
-        for k in (0..puzzle_depth).rev() {
-            let idx = k * slice_area + i;
-            result.push_str(&format!(
-                "{} ",
-                state[idx]
-                    .map(|b| format!(
-                        "{:0>2}",
-                        rot_blocks[b.block_id][b.rot_id].faces[Dir::Left as usize].value
-                    ))
-                    .unwrap_or("".to_string())
-            ));
+    /// Estimate the number of nodes of the whole search tree, as the average of `probes`
+    /// random walks from the root, each one weighted by the branching along its path (Knuth).
+    /// The same seed gives the same estimate.
+    pub fn estimate_nodes(&self, probes: usize, seed: u64) -> f64 {
+        let puzzle = self.puzzle();
+        let mut rng = Rng::new(seed);
+        let mut total = 0.;
+        for _ in 0..probes {
+            let mut probe = puzzle
+                .subproblem(&self.stack[..self.floor])
+                .expect("the floor of a search is a stack of its puzzle");
+            let mut weight = 1.;
+            loop {
+                let children = probe.children();
+                if children.is_empty() {
+                    break;
+                }
+                weight *= children.len() as f64;
+                total += weight;
+                let (block_id, rot_id) = children[rng.below(children.len())];
+                probe.deeper(block_id, rot_id);
+            }
         }
-        result.push('\n');
+        total / probes.max(1) as f64
     }
 
-    result.push_str("Right:\n");
-    for i in (0..puzzle_height).rev() {
-        for k in 0..puzzle_depth {
-            let idx = k * slice_area + (puzzle_width - 1) * puzzle_height + i;
-            result.push_str(&format!(
-                "{} ",
-                state[idx]
-                    .map(|b| format!(
-                        "{:0>2}",
-                        rot_blocks[b.block_id][b.rot_id].faces[Dir::Right as usize].value
-                    ))
-                    .unwrap_or("".to_string())
-            ));
+    // the blocks and rotations which can be placed next
+    fn children(&mut self) -> Vec<(usize, usize)> {
+        let mut children = vec![];
+        for block_id in self.rem.iter().copied().sorted().collect_vec() {
+            for rot_id in 0..self.rot_blocks[block_id].len() {
+                if self.deeper(block_id, rot_id) {
+                    self.pop_block();
+                    children.push((block_id, rot_id));
+                }
+            }
         }
-        result.push('\n');
+        children
     }
 
-    result.push_str("Top:\n");
-    for k in (0..puzzle_depth).rev() {
+    /// Save the state of the search to `path`, to be continued later by `volume::resume`.
+    /// The file is replaced at once, so a checkpoint interrupted midway leaves the previous one.
+    pub fn checkpoint<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let checkpoint = Checkpoint {
+            height: self.puzzle_height,
+            width: self.puzzle_width,
+            depth: self.puzzle_depth,
+            target: self.target,
+            rotations: self.rot_blocks.iter().map(Vec::len).collect_vec(),
+            stack: self.stack.clone(),
+            floor: self.floor,
+            depth_limit: self.depth_limit,
+            done: self.done,
+            retention: self.retention,
+            solutions: self.solutions.clone(),
+            solution_count: self.solution_count,
+            nodes: self.nodes,
+            max_solutions: self.max_solutions,
+            stats: self.stats.clone(),
+            dead_space_pruning: self.dead_space_pruning,
+            side_tiling_pruning: self.side_tiling_pruning,
+            side_sum_pruning: self.side_sum_pruning,
+            symmetry: self.placements.symmetry(),
+            order: self.order(),
+            block_order: self.block_order(),
+            dead_end_cache: self.dead_ends.as_ref().map(DeadEnds::capacity),
+        };
+        let path = path.as_ref();
+        let partial = path.with_extension("partial");
+        let mut out = BufWriter::new(File::create(&partial)?);
+        serde_json::to_writer(&mut out, &checkpoint)?;
+        out.flush()?;
+        drop(out);
+        fs::rename(partial, path)
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.rot_blocks.len()
+    }
+
+    /// The blocks placed so far, as the search sees the puzzle: the box mapped when it fills the
+    /// cells in another order than the default one, the blocks numbered in the order it tries them
+    pub fn stack(&self) -> Vec<(&Block, usize, usize, usize, usize)> {
+        placed_blocks(
+            [self.puzzle_height, self.puzzle_width, self.puzzle_depth],
+            &self.rot_blocks,
+            &self.stack,
+        )
+    }
+
+    /// Same as `stack`, for the blocks of a solution
+    pub fn solution_blocks(
+        &self,
+        solution: &Solution,
+    ) -> Vec<(&Block, usize, usize, usize, usize)> {
+        let (dims, rot_blocks) = self.solution_frame();
+        placed_blocks(dims, rot_blocks, &solution.placements)
+    }
+
+    pub fn solutions(&self) -> Vec<String> {
+        self.solutions
+            .iter()
+            .map(|sol| self.print_solution(sol))
+            .collect_vec()
+    }
+
+    /// The solutions kept so far, according to the retention policy
+    pub fn stored_solutions(&self) -> &[Solution] {
+        &self.solutions
+    }
+
+    /// How many solutions were found so far, whether kept or not
+    pub fn solution_count(&self) -> u128 {
+        self.solution_count
+    }
+
+    /// How many of the 24 rotations of the block look the same as the given one to the search,
+    /// which only keeps one of them: e.g. 8 for a blank block with a square base
+    pub fn rot_multiplicity(&self, block_id: usize, rot_id: usize) -> u64 {
+        let with_values = self.target.is_some();
+        let rot = &self.solution_frame().1[block_id][rot_id];
+        let rot_looks = looks(rot, with_values);
+        all_rots(rot)
+            .iter()
+            .filter(|other| looks(other, with_values) == rot_looks)
+            .count() as u64
+    }
+
+    /// How many solutions the solution stands for, once the rotations of its blocks which look
+    /// the same are told apart
+    pub fn multiplicity(&self, solution: &Solution) -> u128 {
+        solution
+            .placements
+            .iter()
+            .map(|bip| self.rot_multiplicity(bip.block_id, bip.rot_id) as u128)
+            .product()
+    }
+
+    /// Same as `solution_count`, with the rotations of the blocks which look the same told apart
+    pub fn expanded_solution_count(&self) -> u128 {
+        // all the rotations of a block look the same as equally many others
+        let multiplicity: u128 = (0..self.rot_blocks.len())
+            .map(|block_id| self.rot_multiplicity(block_id, 0) as u128)
+            .product();
+        self.solution_count * multiplicity
+    }
+
+    /// The solutions the given symmetries of the box turn the solution into, whatever the
+    /// symmetry the search discounted; with the solution itself
+    pub fn canonical_form(&self, solution: &Solution, symmetry: Symmetry) -> Orbit {
+        let (dims, rot_blocks) = self.solution_frame();
+        let symmetries = PuzzleSymmetry::all(dims, rot_blocks, self.target.is_some(), symmetry);
+        // solutions compare by their placements, in the order of the cells
+        let sorted = |placements: &mut dyn Iterator<Item = BlockInPuzzle>| {
+            placements
+                .sorted_by_key(|bip| (bip.position, bip.block_id, bip.rot_id))
+                .collect_vec()
+        };
+        let images = symmetries
+            .iter()
+            .map(|symmetry| {
+                sorted(&mut solution.placements.iter().map(|bip| {
+                    let block = &rot_blocks[bip.block_id][bip.rot_id];
+                    let size = [
+                        block.height as usize,
+                        block.width as usize,
+                        block.depth as usize,
+                    ];
+                    let (rot_id, position) =
+                        symmetry.map(dims, size, bip.block_id, bip.rot_id, bip.position);
+                    BlockInPuzzle {
+                        block_id: bip.block_id,
+                        rot_id,
+                        position,
+                    }
+                }))
+            })
+            .chain(std::iter::once(sorted(
+                &mut solution.placements.iter().copied(),
+            )))
+            .unique()
+            .collect_vec();
+        let size = images.len();
+        let placements = images
+            .into_iter()
+            .min_by_key(|placements| {
+                placements
+                    .iter()
+                    .map(|bip| (bip.position, bip.block_id, bip.rot_id))
+                    .collect_vec()
+            })
+            .unwrap_or_default();
+        Orbit {
+            solution: Solution { placements },
+            size,
+        }
+    }
+
+    /// The kept solutions grouped by the given symmetries of the box, in order of discovery.
+    /// When the search discounted the same symmetries, each solution is an orbit of its own,
+    /// and the sizes add up to the count of solutions the symmetries did not discount.
+    pub fn orbits(&self, symmetry: Symmetry) -> Vec<Orbit> {
+        self.solutions
+            .iter()
+            .map(|solution| self.canonical_form(solution, symmetry))
+            .unique()
+            .collect_vec()
+    }
+
+    fn print_state(&self) -> String {
+        print_state(
+            self.puzzle_height,
+            self.puzzle_width,
+            self.puzzle_depth,
+            &self.rot_blocks,
+            &cells(
+                [self.puzzle_height, self.puzzle_width, self.puzzle_depth],
+                &self.rot_blocks,
+                &self.stack,
+            ),
+        )
+    }
+
+    fn print_stack_tiny(&self) -> String {
+        let mut result = "[ ".to_string();
+        for bip in &self.stack {
+            result.push_str(&format!("({}, {}) ", bip.block_id, bip.rot_id));
+        }
+        result.push_str("] - sums: ");
+        result.push_str(&format!("{:?}", self.face_sums));
+        result
+    }
+
+    pub fn done(&self) -> bool {
+        self.done
+    }
+
+    /// Which found solutions are kept, to be listed by `solutions`; `Retention::All` by default.
+    /// Use `Retention::Nothing` when only counting them, or when consuming them through the
+    /// `Iterator` implementation.
+    pub fn set_retention(&mut self, retention: Retention) {
+        self.retention = retention;
+    }
+
+    /// Stop the search as soon as `max` solutions are found; `None` (the default) for no limit.
+    /// A search stopped at the previous limit goes on once stepped again, if the new one allows.
+    pub fn set_max_solutions(&mut self, max: Option<u128>) {
+        self.max_solutions = max;
+        // stopped at a limit rather than finished: the last solution is still on the stack
+        if self.done
+            && self.stack.len() > self.floor
+            && max.is_none_or(|max| self.solution_count < max)
+        {
+            self.done = false;
+        }
+    }
+
+    /// Cut the search below any placement which walls off an empty region the remaining blocks
+    /// cannot fill: too narrow for all of them, or of a volume no set of them adds up to.
+    /// Off by default; finds the same solutions, with fewer nodes but more work per node.
+    /// Only used by `Backend::Backtracking`.
+    pub fn set_dead_space_pruning(&mut self, enabled: bool) {
+        self.dead_space_pruning = enabled;
+    }
+
+    /// Cut the search below any placement which leaves a side of the box whose uncovered cells
+    /// cannot be tiled by the faces the remaining blocks can show on it, one face per block.
+    /// Off by default; only used by `Backend::Backtracking`.
+    pub fn set_side_tiling_pruning(&mut self, enabled: bool) {
+        self.side_tiling_pruning = enabled;
+    }
+
+    /// Cut the search below any placement which leaves a side of the box the remaining blocks
+    /// cannot complete to the target sum, whatever faces they show on it.
+    /// On by default; finds the same solutions either way. Only used with a target sum.
+    pub fn set_side_sum_pruning(&mut self, enabled: bool) {
+        self.side_sum_pruning = enabled;
+    }
+
+    /// Remember up to `capacity` states the search found no solution from: the same cells taken,
+    /// with the same blocks left and side sums, are cut at once when reached again by placing
+    /// the blocks in another order. `None` (the default) for no cache; finds the same solutions
+    /// either way. Only filled by `Backend::Backtracking`.
+    pub fn set_dead_end_cache(&mut self, capacity: Option<usize>) {
+        let cells = self.puzzle_height * self.puzzle_width * self.puzzle_depth;
+        let blocks = self.rot_blocks.len();
+        self.dead_ends = capacity.map(|capacity| DeadEnds::new(capacity, cells, blocks));
+    }
+
+    /// Whether the puzzle has exactly one solution, counting the solutions found so far.
+    /// The search goes on to the second solution at most, then gets its solution limit back.
+    pub fn is_unique(&mut self) -> bool {
+        let max = self.max_solutions;
+        self.set_max_solutions(Some(2));
+        while self.step() {}
+        self.set_max_solutions(max);
+        self.solution_count == 1
+    }
+
+    /// Register a sink, to be handed every solution as soon as it is found
+    pub fn add_sink(&mut self, sink: Box<dyn SolutionSink + Send>) {
+        self.sinks.push(sink);
+    }
+
+    /// Register an observer, to be notified of the search events
+    pub fn add_observer(&mut self, observer: Box<dyn SearchObserver + Send>) {
+        self.observers.push(observer);
+    }
+
+    fn notify<F: FnMut(&mut dyn SearchObserver)>(&mut self, mut event: F) {
+        if let Some(stats) = &mut self.stats {
+            event(stats);
+        }
+        for observer in &mut self.observers {
+            event(observer.as_mut());
+        }
+    }
+
+    /// Start collecting search statistics, from scratch
+    pub fn enable_stats(&mut self) {
+        self.stats = Some(SearchStats::default());
+    }
+
+    /// The statistics collected so far, if enabled
+    pub fn stats(&self) -> Option<&SearchStats> {
+        self.stats.as_ref()
+    }
+
+    /// Run the search to the end with the given algorithm. Both find the same solutions,
+    /// not necessarily in the same order. The exact cover search starts over from the floor
+    /// of the solver (the empty puzzle, unless a subproblem), and observers are only told
+    /// about its solutions.
+    pub fn run(&mut self, backend: Backend) {
+        match backend {
+            Backend::Backtracking => while self.step() {},
+            Backend::ExactCover => self.run_exact_cover(),
+            Backend::MostConstrained => {
+                while self.stack.len() > self.floor {
+                    self.pop_block();
+                }
+                self.most_constrained();
+                self.done = true;
+            }
+        }
+    }
+
+    // place the remaining blocks at the most constrained cells, depth first,
+    // until the search is over or is to stop
+    fn most_constrained(&mut self) {
+        let floor = self.stack.len();
+        // by depth, the placements left to try at the cell the search is at;
+        // none once the puzzle is full
+        let mut candidates = vec![self.most_constrained_cell().into_iter()];
+        while let Some(next) = candidates.last_mut().map(Iterator::next) {
+            if let Some((position, index)) = next {
+                self.position = position;
+                if self.deeper_fit(index) {
+                    candidates.push(self.most_constrained_cell().into_iter());
+                }
+                continue;
+            }
+            // all tried: remove the block placed to get there, or all of them once to stop
+            candidates.pop();
+            if candidates.is_empty() {
+                return;
+            }
+            let stop = self
+                .max_solutions
+                .is_some_and(|max| self.solution_count >= max);
+            let depth = if stop {
+                floor
+            } else {
+                floor + candidates.len() - 1
+            };
+            while self.stack.len() > depth {
+                let top = self.pop_block();
+                let depth = self.stack.len();
+                self.notify(|o| o.block_removed(&top, depth));
+            }
+            if stop {
+                return;
+            }
+        }
+    }
+
+    // the placements of the remaining blocks which fit the empty cell with the fewest of them,
+    // as positions and indexes in the placement tables.
+    // Only the corners of the empty space are looked at: the cells next to a taken cell or the
+    // box on one side along each axis, where a block filling the cell has its corner.
+    fn most_constrained_cell(&self) -> Vec<(usize, usize)> {
+        let dims = [self.puzzle_height, self.puzzle_width, self.puzzle_depth];
+        let taken = |corner: [usize; 3], axis: usize, high: bool| {
+            let mut next = corner;
+            if high {
+                next[axis] += 1;
+                if next[axis] == dims[axis] {
+                    return true;
+                }
+            } else {
+                if next[axis] == 0 {
+                    return true;
+                }
+                next[axis] -= 1;
+            }
+            self.occupied
+                .contains(self.index(next[0], next[1], next[2]))
+        };
+        let mut best: Option<Vec<(usize, usize)>> = None;
+        for cell in 0..dims.iter().product() {
+            if self.occupied.contains(cell) {
+                continue;
+            }
+            let (x, y, z) = self.coords(cell);
+            let corner = [x, y, z];
+            // by axis, whether the blocks filling the cell end there rather than start there
+            let mut high = [false; 3];
+            if !(0..3).all(|axis| {
+                high[axis] = !taken(corner, axis, false);
+                !high[axis] || taken(corner, axis, true)
+            }) {
+                continue;
+            }
+            let mut fits = vec![];
+            for block_id in (0..self.rot_blocks.len()).filter(|id| self.rem.contains(id)) {
+                for (rot_id, block) in self.rot_blocks[block_id].iter().enumerate() {
+                    let size = [
+                        block.height as usize,
+                        block.width as usize,
+                        block.depth as usize,
+                    ];
+                    let Some([x, y, z]) = (0..3)
+                        .map(|axis| {
+                            if high[axis] {
+                                (corner[axis] + 1).checked_sub(size[axis])
+                            } else {
+                                Some(corner[axis])
+                            }
+                        })
+                        .collect::<Option<Vec<_>>>()
+                        .map(|low| [low[0], low[1], low[2]])
+                    else {
+                        continue;
+                    };
+                    let position = self.index(x, y, z);
+                    let Some(index) = self.placements.find(position, block_id, rot_id) else {
+                        continue;
+                    };
+                    if self.placements.fits(position)[index].canonical
+                        && !self
+                            .occupied
+                            .intersects_at(self.placements.mask(block_id, rot_id), position)
+                    {
+                        fits.push((position, index));
+                    }
+                }
+                if best.as_ref().is_some_and(|best| fits.len() >= best.len()) {
+                    break;
+                }
+            }
+            if best.as_ref().is_none_or(|best| fits.len() < best.len()) {
+                let empty = fits.is_empty();
+                best = Some(fits);
+                if empty {
+                    break;
+                }
+            }
+        }
+        best.unwrap_or_default()
+    }
+
+    fn run_exact_cover(&mut self) {
+        while self.stack.len() > self.floor {
+            self.pop_block();
+        }
+        // columns: the cells, then the blocks
+        let volume = self.puzzle_height * self.puzzle_width * self.puzzle_depth;
+        let mut dlx = Dlx::new(volume + self.block_count(), 0);
+        let mut rows = vec![];
+        let mut selected = vec![];
+        for position in 0..volume {
+            let (x, y, z) = self.coords(position);
+            // the placements left out by symmetry are not searched either
+            for fit in self
+                .placements
+                .fits(position)
+                .iter()
+                .filter(|fit| fit.canonical)
+            {
+                let block = &self.rot_blocks[fit.block_id][fit.rot_id];
+                let mut columns = vec![];
+                for k in z..z + block.depth as usize {
+                    for j in y..y + block.width as usize {
+                        for i in x..x + block.height as usize {
+                            columns.push(self.index(i, j, k));
+                        }
+                    }
+                }
+                columns.push(volume + fit.block_id);
+                let row = dlx.add_row(&columns);
+                let bip = BlockInPuzzle {
+                    block_id: fit.block_id,
+                    rot_id: fit.rot_id,
+                    position,
+                };
+                if self.stack.contains(&bip) {
+                    selected.push(row);
+                }
+                rows.push(CoverRow {
+                    bip,
+                    touched: fit.touched,
+                    values: std::array::from_fn(|idx| block.faces[idx].value),
+                    areas: std::array::from_fn(|idx| block.faces[idx].area()),
+                });
+            }
+        }
+
+        // the rows must all be in, for the selected ones to take the others out
+        for row in selected {
+            dlx.select(row);
+        }
+        let placements = Arc::clone(&self.placements);
+        let mut sides = SideSums {
+            target: self.target,
+            face_sums: self.face_sums,
+            face_free_areas: self.face_free_areas,
+            rem: placements
+                .side_sum_set(&self.rem)
+                .filter(|_| self.side_sum_pruning),
+            placements: &placements,
+            rows: &rows,
+            pushes: 0,
+        };
+        let prefix = self.stack.clone();
+        if self
+            .max_solutions
+            .is_none_or(|max| self.solution_count < max)
+        {
+            dlx.search(&mut sides, |picked| {
+                let mut solution = prefix.clone();
+                solution.extend(picked.iter().map(|&row| rows[row].bip));
+                // in placement order, as the backtracking search would have them
+                solution.sort_by_key(|bip| bip.position);
+                if placements.is_canonical(&packing(&solution)) {
+                    self.accept_solution(self.solution_of(solution));
+                }
+                self.max_solutions
+                    .is_none_or(|max| self.solution_count < max)
+            });
+        }
+        self.nodes += sides.pushes;
+        self.done = true;
+    }
+
+    /// Run the search to the end without keeping any solution, returning the total count.
+    /// Once the search is over, returns the same count again.
+    pub fn count_solutions(&mut self) -> u128 {
+        self.retention = Retention::Nothing;
+        while self.step() {}
+        self.solution_count
+    }
+
+    // true when the current state is a complete packing
+    fn solved(&self) -> bool {
+        self.position == self.puzzle_height * self.puzzle_width * self.puzzle_depth
+    }
+
+    fn current_solution(&self) -> Solution {
+        self.solution_of(self.stack.clone())
+    }
+
+    /// Render a solution the same way `solutions` does
+    pub fn print_solution(&self, solution: &Solution) -> String {
+        let (dims, rot_blocks) = self.solution_frame();
+        let [height, width, depth] = dims;
+        print_state(
+            height,
+            width,
+            depth,
+            rot_blocks,
+            &cells(dims, rot_blocks, &solution.placements),
+        )
+    }
+
+    fn coords(&self, position: usize) -> (usize, usize, usize) {
+        let slice_area = self.puzzle_height * self.puzzle_width;
+        (
+            position % self.puzzle_height,
+            (position % slice_area) / self.puzzle_height,
+            position / slice_area,
+        )
+    }
+
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        (z * self.puzzle_width + y) * self.puzzle_height + x
+    }
+
+    // the next solution, as the iterator hands it over; none once `stop` is set,
+    // as checked between steps
+    pub(crate) fn next_solution(&mut self, stop: Option<&AtomicBool>) -> Option<Solution> {
+        loop {
+            if stop.is_some_and(|stop| stop.load(Ordering::Relaxed)) {
+                return None;
+            }
+            if !self.step() {
+                return None;
+            }
+            if self.solved() {
+                return Some(self.current_solution());
+            }
+        }
+    }
+
+    pub fn step_to_solution(&mut self) -> bool {
+        while self.step() {
+            if self.solved() {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Step at most `max_nodes` times
+    pub fn step_n(&mut self, max_nodes: u64) -> StepOutcome {
+        let mut nodes = 0;
+        self.run_bounded(false, || {
+            nodes += 1;
+            nodes <= max_nodes
+        })
+    }
+
+    /// Step until `budget` is spent; the clock is checked between steps.
+    /// Not available on wasm, where there is no clock: use `step_n` instead.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn step_for(&mut self, budget: Duration) -> StepOutcome {
+        let deadline = Instant::now() + budget;
+        self.run_bounded(false, || Instant::now() < deadline)
+    }
+
+    /// Same as `step_to_solution`, giving up after `max_nodes` steps
+    pub fn step_to_solution_n(&mut self, max_nodes: u64) -> StepOutcome {
+        let mut nodes = 0;
+        self.run_bounded(true, || {
+            nodes += 1;
+            nodes <= max_nodes
+        })
+    }
+
+    /// A token to stop the bounded runs (`step_n`, `step_for`...) of this solver,
+    /// e.g. from another thread. Once cancelled, they return right away.
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    fn run_bounded<F: FnMut() -> bool>(
+        &mut self,
+        to_solution: bool,
+        mut budget_left: F,
+    ) -> StepOutcome {
+        // whatever the budget, a finished search stays finished
+        if self.done {
+            return StepOutcome::Finished;
+        }
+        loop {
+            if self.cancel.is_cancelled() {
+                return StepOutcome::Cancelled;
+            }
+            if !budget_left() {
+                return StepOutcome::Paused;
+            }
+            if !self.step() {
+                return StepOutcome::Finished;
+            }
+            if to_solution && self.solved() {
+                return StepOutcome::Solved;
+            }
+        }
+    }
+
+    fn trace_deeper(&self) {
+        let bip = self.stack.last().unwrap();
+        trace!(
+            "deeper ({}={}), new block {} {}, rot {} - rem {:?}",
+            self.stack.len(),
+            self.print_stack_tiny(),
+            bip.block_id,
+            self.rot_blocks[bip.block_id][bip.rot_id].label,
+            bip.rot_id,
+            self.rem
+        );
+    }
+
+    pub fn step(&mut self) -> bool {
+        trace!("step");
+        // the search is over for good, rather than started over
+        if self.done {
+            return false;
+        }
+        if self.limit_reached() {
+            trace!("solution limit reached, done");
+            self.done = true;
+            return false;
+        }
+        if !self.rem.is_empty()
+            && self
+                .depth_limit
+                .is_none_or(|limit| self.stack.len() < limit)
+        {
+            trace!("deeper?");
+            // case 1:
+            // try to go deeper (place a new block)
+            // the blocks are tried by id, then rotation, as laid out in the placement tables
+            for index in 0..self.placements.fits(self.position).len() {
+                let fit = self.placements.fits(self.position)[index];
+                if self.rem.contains(&fit.block_id) && self.deeper_fit(index) {
+                    self.trace_deeper();
+                    return true;
+                }
+            }
+        }
+
+        trace!("not deeper, sideway?");
+        // cant place a new block ->
+        // case 4:
+        // cant move sideway, cant backtrack, the end
+        while self.stack.len() > self.floor {
+            // case 2:
+            // move sideway in the graph (replace the block at the top of the stack)
+            // case 3:
+            // or backtrack
+            if self.move_sideway_or_backtrack() {
+                return true;
+            }
+        }
+        trace!("not sideway, done");
+        self.done = true;
+        false
+    }
+
+    fn move_sideway_or_backtrack(&mut self) -> bool {
+        self.remember_dead_end();
+        // backtrack
+        let top = self.pop_block();
+        let block_id = top.block_id;
+        let rot_id = top.rot_id;
+        let depth = self.stack.len();
+        self.notify(|o| o.block_removed(&top, depth));
+
+        // try placing again the same block with a different rot, then a sibling:
+        // the fits after the removed one
+        let start = self
+            .placements
+            .find(top.position, block_id, rot_id)
+            .expect("a placed block fits")
+            + 1;
+        for index in start..self.placements.fits(self.position).len() {
+            let fit = self.placements.fits(self.position)[index];
+            if fit.block_id != block_id && !self.rem.contains(&fit.block_id) {
+                continue;
+            }
+            if self.deeper_fit(index) {
+                trace!(
+                    "sideway ({}={}), new block {}, rot {} - rem {:?}",
+                    self.stack.len(),
+                    self.print_stack_tiny(),
+                    fit.block_id,
+                    fit.rot_id,
+                    self.rem
+                );
+                return true;
+            } else {
+                trace!(
+                    "skipped block {}, rot {} (doesnt fit)",
+                    fit.block_id,
+                    fit.rot_id
+                );
+            }
+        }
+        // stay backtracked
+        trace!(
+            "not sideway, backtracking ({}={}) - rem {:?}",
+            self.stack.len(),
+            self.print_stack_tiny(),
+            self.rem
+        );
+        false
+    }
+
+    // the Zobrist key of the state of the search
+    fn state_key(&self) -> u64 {
+        self.keys.last().copied().unwrap_or(0) ^ self.placements.sums_key(&self.face_sums)
+    }
+
+    // whether the state of the search is known to lead to no solution
+    fn known_dead_end(&self) -> bool {
+        self.dead_ends.as_ref().is_some_and(|dead_ends| {
+            dead_ends.contains(self.state_key(), &self.occupied, &self.rem, &self.face_sums)
+        })
+    }
+
+    // remember the state of the search, once everything below the block on top of the stack
+    // was searched without completing a packing; a packing rejected as symmetric from this
+    // stack could complete the same state reached from another one
+    fn remember_dead_end(&mut self) {
+        if self.dead_ends.is_none()
+            || self.depth_limit.is_some()
+            || self.completions_at.last() != Some(&self.completions)
+        {
+            return;
+        }
+        let key = self.state_key();
+        if let Some(dead_ends) = &mut self.dead_ends {
+            dead_ends.insert(key, &self.occupied, &self.rem, &self.face_sums);
+        }
+    }
+
+    fn remove_block_from_face_state(&mut self, bip: BlockInPuzzle) {
+        if self.target.is_some() {
+            let block = &self.rot_blocks[bip.block_id][bip.rot_id];
+            let index = self
+                .placements
+                .find(bip.position, bip.block_id, bip.rot_id)
+                .expect("a placed block fits");
+            let touched = self.placements.fits(bip.position)[index].touched;
+            for (idx, _) in touched.iter().enumerate().filter(|(_, &t)| t) {
+                self.face_sums[idx] -= block.faces[idx].value;
+                self.face_free_areas[idx] += block.faces[idx].area();
+            }
+        }
+    }
+
+    fn remove_block_from_state(&mut self, bip: BlockInPuzzle) {
+        self.occupied
+            .remove_at(self.placements.mask(bip.block_id, bip.rot_id), bip.position);
+    }
+
+    // try to go deeper in the solution graph, by placing one more rotated block
+    fn deeper(&mut self, block_id: usize, rot_id: usize) -> bool {
+        match self.placements.find(self.position, block_id, rot_id) {
+            Some(index) => self.deeper_fit(index),
+            None => {
+                let bip = BlockInPuzzle {
+                    block_id,
+                    rot_id,
+                    position: self.position,
+                };
+                trace!("block sticks out");
+                self.notify(|o| o.placement_rejected(&bip, Rejection::SticksOut));
+                false
+            }
+        }
+    }
+
+    // a side whose free area the remaining blocks cannot cover with faces adding up to
+    // what the side sum lacks, if any
+    fn unreachable_side(&self) -> Option<Dir> {
+        let target = self.target.filter(|_| self.side_sum_pruning)?;
+        let set = self.placements.side_sum_set(&self.rem)?;
+        SIDE_CHECK_ORDER.into_iter().find(|&dir| {
+            let idx = dir as usize;
+            !self.placements.side_sum_reachable(
+                set,
+                dir,
+                self.face_free_areas[idx] as usize,
+                (target - self.face_sums[idx]) as usize,
+            )
+        })
+    }
+
+    // a side the block on top of the stack shows on, whose uncovered cells the faces of the
+    // remaining blocks cannot tile, if any
+    fn untileable_side(&self, touched: [bool; 6]) -> Option<Dir> {
+        if !self.side_tiling_pruning || self.solved() {
+            return None;
+        }
+        SIDE_CHECK_ORDER.into_iter().find(|&dir| {
+            if !touched[dir as usize] || self.face_free_areas[dir as usize] == 0 {
+                return false;
+            }
+            let (columns, rows, cells) = self.side_grid(dir);
+            let region = cells
+                .iter()
+                .map(|&cell| !self.occupied.contains(cell))
+                .collect_vec();
+            let pieces = self
+                .rem
+                .iter()
+                .sorted()
+                .map(|&block_id| {
+                    self.rot_blocks[block_id]
+                        .iter()
+                        .map(|block| {
+                            let (a, b) = side_rect(block, dir);
+                            (a.max(b), a.min(b))
+                        })
+                        .unique()
+                        .sorted()
+                        .collect_vec()
+                })
+                .collect_vec();
+            tile_region(columns, rows, &region, &pieces, false).is_none()
+        })
+    }
+
+    // the cells of a side of the box, as a grid of columns x rows, row by row
+    fn side_grid(&self, dir: Dir) -> (usize, usize, Vec<usize>) {
+        let (h, w, d) = (self.puzzle_height, self.puzzle_width, self.puzzle_depth);
+        match dir {
+            Dir::Front | Dir::Back => {
+                let z = if dir == Dir::Front { 0 } else { d - 1 };
+                let cells = (0..w)
+                    .flat_map(|y| (0..h).map(move |x| (x, y)))
+                    .map(|(x, y)| self.index(x, y, z));
+                (h, w, cells.collect_vec())
+            }
+            Dir::Left | Dir::Right => {
+                let y = if dir == Dir::Left { 0 } else { w - 1 };
+                let cells = (0..d)
+                    .flat_map(|z| (0..h).map(move |x| (x, z)))
+                    .map(|(x, z)| self.index(x, y, z));
+                (h, d, cells.collect_vec())
+            }
+            Dir::Top | Dir::Bottom => {
+                let x = if dir == Dir::Bottom { 0 } else { h - 1 };
+                let cells = (0..d)
+                    .flat_map(|z| (0..w).map(move |y| (y, z)))
+                    .map(|(y, z)| self.index(x, y, z));
+                (w, d, cells.collect_vec())
+            }
+        }
+    }
+
+    // whether the block on top of the stack walls off an empty region the remaining blocks
+    // cannot fill; only the regions next to that block can be new
+    fn dead_space(&self) -> bool {
+        let top = self.stack.last().unwrap();
+        let block = &self.rot_blocks[top.block_id][top.rot_id];
+        let (x0, y0, z0) = self.coords(top.position);
+        let (x1, y1, z1) = (
+            x0 + block.height as usize,
+            y0 + block.width as usize,
+            z0 + block.depth as usize,
+        );
+        let volumes = self
+            .rem
+            .iter()
+            .map(|&block_id| {
+                let block = &self.rot_blocks[block_id][0];
+                block.height as usize * block.width as usize * block.depth as usize
+            })
+            .collect_vec();
+        // at most one region is over half the free volume: the one the search goes on in
+        let cap = volumes.iter().sum::<usize>() / 2;
+
+        let mut seeds = vec![];
+        for y in y0..y1 {
+            for z in z0..z1 {
+                seeds.extend(x0.checked_sub(1).map(|x| (x, y, z)));
+                seeds.extend((x1 < self.puzzle_height).then_some((x1, y, z)));
+            }
+        }
+        for x in x0..x1 {
+            for z in z0..z1 {
+                seeds.extend(y0.checked_sub(1).map(|y| (x, y, z)));
+                seeds.extend((y1 < self.puzzle_width).then_some((x, y1, z)));
+            }
+            for y in y0..y1 {
+                seeds.extend(z0.checked_sub(1).map(|z| (x, y, z)));
+                seeds.extend((z1 < self.puzzle_depth).then_some((x, y, z1)));
+            }
+        }
+
+        let mut seen = self.occupied.clone();
+        let mut big = BitSet::new(self.puzzle_height * self.puzzle_width * self.puzzle_depth);
+        seeds.into_iter().any(|(x, y, z)| {
+            let cell = self.index(x, y, z);
+            !seen.contains(cell)
+                && self
+                    .region(cell, cap, &mut seen, &mut big)
+                    .is_some_and(|(size, extent)| !self.fillable(size, extent, &volumes))
+        })
+    }
+
+    // the size and the bounding box dimensions of the empty region around `start`,
+    // unless it is over `cap` cells or joins such a region, whose cells go to `big`
+    fn region(
+        &self,
+        start: usize,
+        cap: usize,
+        seen: &mut BitSet,
+        big: &mut BitSet,
+    ) -> Option<(usize, [usize; 3])> {
+        let (x, y, z) = self.coords(start);
+        let (mut min, mut max) = ([x, y, z], [x, y, z]);
+        let mut cells = vec![start];
+        seen.insert(start);
+        let mut next = 0;
+        while next < cells.len() {
+            let (x, y, z) = self.coords(cells[next]);
+            next += 1;
+            let neighbours = [
+                x.checked_sub(1).map(|x| (x, y, z)),
+                (x + 1 < self.puzzle_height).then_some((x + 1, y, z)),
+                y.checked_sub(1).map(|y| (x, y, z)),
+                (y + 1 < self.puzzle_width).then_some((x, y + 1, z)),
+                z.checked_sub(1).map(|z| (x, y, z)),
+                (z + 1 < self.puzzle_depth).then_some((x, y, z + 1)),
+            ];
+            for (x, y, z) in neighbours.into_iter().flatten() {
+                let cell = self.index(x, y, z);
+                if big.contains(cell) || cells.len() > cap {
+                    cells.iter().for_each(|&cell| big.insert(cell));
+                    return None;
+                }
+                if !seen.contains(cell) {
+                    seen.insert(cell);
+                    cells.push(cell);
+                    for (axis, coord) in [x, y, z].into_iter().enumerate() {
+                        min[axis] = min[axis].min(coord);
+                        max[axis] = max[axis].max(coord);
+                    }
+                }
+            }
+        }
+        Some((cells.len(), [0, 1, 2].map(|axis| max[axis] - min[axis] + 1)))
+    }
+
+    // whether some remaining block fits in the region bounds, and some of them add up to its size
+    fn fillable(&self, size: usize, extent: [usize; 3], volumes: &[usize]) -> bool {
+        let fits = self.rem.iter().any(|&block_id| {
+            self.rot_blocks[block_id].iter().any(|block| {
+                block.height as usize <= extent[0]
+                    && block.width as usize <= extent[1]
+                    && block.depth as usize <= extent[2]
+            })
+        });
+        if !fits {
+            return false;
+        }
+        let mut sums = vec![false; size + 1];
+        sums[0] = true;
+        for &volume in volumes {
+            for sum in (volume..=size).rev() {
+                sums[sum] |= sums[sum - volume];
+            }
+        }
+        sums[size]
+    }
+
+    // same as `deeper`, for the given fit at the current position
+    fn deeper_fit(&mut self, index: usize) -> bool {
+        let fit = self.placements.fits(self.position)[index];
+        let bip = BlockInPuzzle {
+            block_id: fit.block_id,
+            rot_id: fit.rot_id,
+            position: self.position,
+        };
+        if !fit.canonical {
+            trace!("symmetric to another placement");
+            self.notify(|o| o.placement_rejected(&bip, Rejection::Symmetric));
+            return false;
+        }
+        match self.place_3d(bip, fit.touched) {
+            Ok(new_position) => {
+                self.stack.push(bip);
+                self.keys
+                    .push(self.keys.last().copied().unwrap_or(0) ^ fit.key);
+                self.completions_at.push(self.completions);
+                self.rem.remove(&bip.block_id);
+                self.position = new_position;
+                if self.rem.is_empty() {
+                    self.completions += 1;
+                }
+                if let Some(dir) = self.unreachable_side() {
+                    trace!("{:?} sum out of reach", dir);
+                    self.pop_block();
+                    self.notify(|o| o.placement_rejected(&bip, Rejection::SideSumUnreachable(dir)));
+                    return false;
+                }
+                if self.known_dead_end() {
+                    trace!("known dead end");
+                    self.pop_block();
+                    self.notify(|o| o.placement_rejected(&bip, Rejection::KnownDeadEnd));
+                    return false;
+                }
+                if let Some(dir) = self.untileable_side(fit.touched) {
+                    trace!("{:?} cannot be tiled", dir);
+                    self.pop_block();
+                    self.notify(|o| o.placement_rejected(&bip, Rejection::SideUntileable(dir)));
+                    return false;
+                }
+                if self.dead_space_pruning && !self.solved() && self.dead_space() {
+                    trace!("dead space");
+                    self.pop_block();
+                    self.notify(|o| o.placement_rejected(&bip, Rejection::DeadSpace));
+                    return false;
+                }
+                if self.solved() && !self.placements.is_canonical(&packing(&self.stack)) {
+                    trace!("symmetric to another packing");
+                    self.pop_block();
+                    self.notify(|o| o.placement_rejected(&bip, Rejection::Symmetric));
+                    return false;
+                }
+                self.nodes += 1;
+                let depth = self.stack.len();
+                self.notify(|o| o.block_placed(&bip, depth));
+                if self.solved() {
+                    self.record_solution();
+                }
+                true
+            }
+            Err(rejection) => {
+                self.notify(|o| o.placement_rejected(&bip, rejection));
+                false
+            }
+        }
+    }
+
+    fn record_solution(&mut self) {
+        let solution = self.print_state();
+        debug!("solution:\n{}\n{}", &solution, self.print_stack_tiny());
+        self.accept_solution(self.current_solution());
+    }
+
+    fn accept_solution(&mut self, solution: Solution) {
+        self.solution_count += 1;
+        // sinks get to look at the solver too, so they're set aside meanwhile
+        let mut sinks = std::mem::take(&mut self.sinks);
+        for sink in &mut sinks {
+            if let Err(e) = sink.accept(self, &solution) {
+                error!("solution sink failed: {}", e);
+            }
+        }
+        self.sinks = sinks;
+        self.notify(|o| o.solution_found(&solution));
+        if self.keeps_more() {
+            self.solutions.push(solution);
+        }
+    }
+
+    // whether the retention policy allows storing another solution
+    fn keeps_more(&self) -> bool {
+        match self.retention {
+            Retention::All => true,
+            Retention::Sample(n) => self.solutions.len() < n,
+            Retention::Nothing => false,
+        }
+    }
+
+    pub(crate) fn init(&mut self) -> bool {
+        trace!("init");
+        self.deeper(0, 0);
+        trace!(
+            "deeper ({}={}), new block {}, rot {} - rem {:?}",
+            self.stack.len(),
+            self.print_stack_tiny(),
+            0,
+            0,
+            self.rem
+        );
+        true
+    }
+
+    // place a block known to stay within the puzzle, showing on the `touched` sides
+    fn place_3d(&mut self, bip: BlockInPuzzle, touched: [bool; 6]) -> Result<usize, Rejection> {
+        let block = &self.rot_blocks[bip.block_id][bip.rot_id];
+        let mask = self.placements.mask(bip.block_id, bip.rot_id);
+
+        if let Some(target_sum) = self.target {
+            // if a puzzle face is completed, its value must add up to target_sum
+            for dir in SIDE_CHECK_ORDER {
+                let idx = dir as usize;
+                if !touched[idx] {
+                    continue;
+                }
+                let new_sum = self.face_sums[idx] + block.faces[idx].value;
+                if new_sum > target_sum {
+                    trace!("{:?} sum too big", dir);
+                    return Err(Rejection::SideSumTooBig(dir));
+                }
+                if new_sum == target_sum {
+                    if self.occupied.intersects_at(mask, bip.position) {
+                        trace!("{:?} there's a block here", dir);
+                        return Err(Rejection::Overlap);
+                    }
+                    if !self.occupied.covers_with(
+                        mask,
+                        bip.position,
+                        self.placements.side_mask(dir),
+                    ) {
+                        trace!("{:?} sum==target_sum but face not full", dir);
+                        return Err(Rejection::SideSumReachedNotFull(dir));
+                    }
+                }
+                if new_sum < target_sum && self.face_free_areas[idx] == block.faces[idx].area() {
+                    trace!("{:?} full but sum too small", dir);
+                    return Err(Rejection::SideFullSumShort(dir));
+                }
+            }
+        }
+
+        // only if the block fits...
+        if self.occupied.intersects_at(mask, bip.position) {
+            trace!("there's a block here");
+            return Err(Rejection::Overlap);
+        }
+        // ... is the state updated
+        self.occupied.insert_at(mask, bip.position);
+
+        if self.target.is_some() {
+            for (idx, _) in touched.iter().enumerate().filter(|(_, &t)| t) {
+                self.face_sums[idx] += block.faces[idx].value;
+                self.face_free_areas[idx] -= block.faces[idx].area();
+            }
+        }
+
+        // the cells before the block are all taken, but when placing at the most constrained cell
+        Ok(self.occupied.first_empty_from(0))
+    }
+}
+
+// the blocks placed in a box of the given dimensions, with their id and coordinates
+fn placed_blocks<'a>(
+    dims: [usize; 3],
+    rot_blocks: &'a [Vec<Block>],
+    bips: &[BlockInPuzzle],
+) -> Vec<(&'a Block, usize, usize, usize, usize)> {
+    let [height, width, _] = dims;
+    bips.iter()
+        .map(|bip| {
+            let x = bip.position % height;
+            let y = bip.position / height % width;
+            let z = bip.position / (height * width);
+            (&rot_blocks[bip.block_id][bip.rot_id], bip.block_id, x, y, z)
+        })
+        .collect_vec()
+}
+
+// the block filling each cell of a box of the given dimensions, once the given blocks are placed
+fn cells(
+    dims: [usize; 3],
+    rot_blocks: &[Vec<Block>],
+    placements: &[BlockInPuzzle],
+) -> Vec<Option<BlockInPuzzle>> {
+    let [height, width, depth] = dims;
+    let mut state = vec![None; height * width * depth];
+    for (&bip, (block, _, x_start, y_start, z_start)) in placements
+        .iter()
+        .zip(placed_blocks(dims, rot_blocks, placements))
+    {
+        for k in z_start..z_start + block.depth as usize {
+            for j in y_start..y_start + block.width as usize {
+                for i in x_start..x_start + block.height as usize {
+                    state[(k * width + j) * height + i] = Some(bip);
+                }
+            }
+        }
+    }
+    state
+}
+
+// the placements as (block id, rotation id, position) triples
+fn packing(placements: &[BlockInPuzzle]) -> Vec<(usize, usize, usize)> {
+    placements
+        .iter()
+        .map(|bip| (bip.block_id, bip.rot_id, bip.position))
+        .collect_vec()
+}
+
+// the dimensions of a block along the columns and rows of `Solver::side_grid`
+fn side_rect(block: &Block, dir: Dir) -> (usize, usize) {
+    let (h, w, d) = (
+        block.height as usize,
+        block.width as usize,
+        block.depth as usize,
+    );
+    match dir {
+        Dir::Front | Dir::Back => (h, w),
+        Dir::Left | Dir::Right => (h, d),
+        Dir::Top | Dir::Bottom => (w, d),
+    }
+}
+
+// a placement, as a row of the exact cover matrix
+struct CoverRow {
+    bip: BlockInPuzzle,
+    touched: [bool; 6],
+    // of the block faces, by `Dir` index
+    values: [u8; 6],
+    areas: [u8; 6],
+}
+
+// the side sum rules of `place_3d`, for the exact cover search
+struct SideSums<'a> {
+    target: Option<u8>,
+    face_sums: [u8; 6],
+    face_free_areas: [u8; 6],
+    // the blocks not in the partial cover, as a set of the side sum table,
+    // when checked to still be able to complete every side
+    rem: Option<usize>,
+    placements: &'a Placements,
+    rows: &'a [CoverRow],
+    pushes: u64,
+}
+
+impl Constraint for SideSums<'_> {
+    fn push(&mut self, index: usize) -> bool {
+        let row = &self.rows[index];
+        if let Some(target) = self.target {
+            for idx in (0..6).filter(|&idx| row.touched[idx]) {
+                let sum = self.face_sums[idx] + row.values[idx];
+                let full = self.face_free_areas[idx] == row.areas[idx];
+                // a side is to reach the target sum exactly when it is full
+                if sum > target || (sum == target) != full {
+                    return false;
+                }
+            }
+            for idx in (0..6).filter(|&idx| row.touched[idx]) {
+                self.face_sums[idx] += row.values[idx];
+                self.face_free_areas[idx] -= row.areas[idx];
+            }
+            if let Some(rem) = &mut self.rem {
+                *rem &= !(1 << row.bip.block_id);
+            }
+            // the blocks left must still be able to complete every side
+            let reachable = self.rem.is_none_or(|rem| {
+                SIDE_CHECK_ORDER.into_iter().all(|dir| {
+                    let idx = dir as usize;
+                    self.placements.side_sum_reachable(
+                        rem,
+                        dir,
+                        self.face_free_areas[idx] as usize,
+                        (target - self.face_sums[idx]) as usize,
+                    )
+                })
+            });
+            if !reachable {
+                self.pop(index);
+                return false;
+            }
+        }
+        self.pushes += 1;
+        true
+    }
+
+    fn pop(&mut self, index: usize) {
+        let row = &self.rows[index];
+        if self.target.is_some() {
+            for idx in (0..6).filter(|&idx| row.touched[idx]) {
+                self.face_sums[idx] -= row.values[idx];
+                self.face_free_areas[idx] += row.areas[idx];
+            }
+            if let Some(rem) = &mut self.rem {
+                *rem |= 1 << row.bip.block_id;
+            }
+        }
+    }
+}
+
+/// Lazily walks the search, yielding each solution as it is found
+impl Iterator for Solver {
+    type Item = Solution;
+
+    // fused: once the search is over, `None` for good
+    fn next(&mut self) -> Option<Solution> {
+        self.next_solution(None)
+    }
+}
+
+// xorshift64*, enough for sampling the search tree without a dependency
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Rng {
+        // the state must not be 0
+        Rng(seed ^ 0x9e37_79b9_7f4a_7c15 | 1)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // uniform enough in 0..n, for small n
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+fn print_state(
+    puzzle_height: usize,
+    puzzle_width: usize,
+    puzzle_depth: usize,
+    rot_blocks: &[Vec<Block>],
+    state: &[Option<BlockInPuzzle>],
+) -> String {
+    let slice_area = puzzle_height * puzzle_width;
+
+    let mut result = String::new();
+
+    result.push_str("Front:\n");
+    for i in (0..puzzle_height).rev() {
+        for j in 0..puzzle_width {
+            let idx = j * puzzle_height + i;
+            result.push_str(&format!(
+                "{} ",
+                state[idx]
+                    .map(|b| format!(
+                        "{:0>2}",
+                        rot_blocks[b.block_id][b.rot_id].faces[Dir::Front as usize].value
+                    ))
+                    .unwrap_or("".to_string())
+            ));
+        }
+        result.push('\n');
+    }
+
+    result.push_str("Back:\n");
+    for i in (0..puzzle_height).rev() {
+        for j in (0..puzzle_width).rev() {
+            let idx = (puzzle_depth - 1) * slice_area + j * puzzle_height + i;
+            result.push_str(&format!(
+                "{} ",
+                state[idx]
+                    .map(|b| format!(
+                        "{:0>2}",
+                        rot_blocks[b.block_id][b.rot_id].faces[Dir::Back as usize].value
+                    ))
+                    .unwrap_or("".to_string())
+            ));
+        }
+        result.push('\n');
+    }
+
+    result.push_str("Left:\n");
+    for i in (0..puzzle_height).rev() {
+        for k in (0..puzzle_depth).rev() {
+            let idx = k * slice_area + i;
+            result.push_str(&format!(
+                "{} ",
+                state[idx]
+                    .map(|b| format!(
+                        "{:0>2}",
+                        rot_blocks[b.block_id][b.rot_id].faces[Dir::Left as usize].value
+                    ))
+                    .unwrap_or("".to_string())
+            ));
+        }
+        result.push('\n');
+    }
+
+    result.push_str("Right:\n");
+    for i in (0..puzzle_height).rev() {
+        for k in 0..puzzle_depth {
+            let idx = k * slice_area + (puzzle_width - 1) * puzzle_height + i;
+            result.push_str(&format!(
+                "{} ",
+                state[idx]
+                    .map(|b| format!(
+                        "{:0>2}",
+                        rot_blocks[b.block_id][b.rot_id].faces[Dir::Right as usize].value
+                    ))
+                    .unwrap_or("".to_string())
+            ));
+        }
+        result.push('\n');
+    }
+
+    result.push_str("Top:\n");
+    for k in (0..puzzle_depth).rev() {
         for j in 0..puzzle_width {
             let idx = k * slice_area + j * puzzle_height + puzzle_height - 1;
             result.push_str(&format!(
@@ -1038,153 +2715,1152 @@ fn print_state(
                     .unwrap_or("".to_string())
             ));
         }
-        result.push('\n');
+        result.push('\n');
+    }
+
+    result.push_str("Bottom:\n");
+    for k in 0..puzzle_depth {
+        for j in 0..puzzle_width {
+            let idx = k * slice_area + j * puzzle_height;
+            result.push_str(&format!(
+                "{} ",
+                state[idx]
+                    .map(|b| format!(
+                        "{:0>2}",
+                        rot_blocks[b.block_id][b.rot_id].faces[Dir::Bottom as usize].value
+                    ))
+                    .unwrap_or("".to_string())
+            ));
+        }
+        result.push('\n');
+    }
+
+    result
+}
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use itertools::iproduct;
+    use std::time::Instant;
+
+    // four 2x1x1 blocks with distinct labels, any orientation, to fill a 2x2x2 box
+    pub(crate) fn rot_blocks_4x_2x1x1() -> Vec<Vec<Block>> {
+        ["A", "B", "C", "D"]
+            .iter()
+            .map(|&label| {
+                let mut block = TEST_BLOCK_2X1X1.clone();
+                block.label = label;
+                all_shape_rots(&block)
+            })
+            .collect_vec()
+    }
+
+    // five blocks of mixed shapes, to fill a 4x4x5 box: its 80 cells span two words of a bitset,
+    // and the blocks leave pockets and sides no block fits in
+    fn rot_blocks_5x_mixed() -> Vec<Vec<Block>> {
+        [
+            blank_block("A", 4, 4, 2),
+            blank_block("B", 4, 2, 2),
+            blank_block("C", 4, 2, 2),
+            blank_block("D", 4, 2, 1),
+            blank_block("E", 4, 2, 1),
+        ]
+        .iter()
+        .map(all_shape_rots)
+        .collect_vec()
+    }
+
+    // a block of the given size with blank faces
+    fn blank_block(label: &'static str, height: u8, width: u8, depth: u8) -> Block {
+        let face = |dir, a: u8, b: u8| Face {
+            value: 0,
+            long: a.max(b),
+            short: a.min(b),
+            block: 0,
+            dir,
+        };
+        Block {
+            height,
+            width,
+            depth,
+            faces: [
+                face(Dir::Front, height, width),
+                face(Dir::Back, height, width),
+                face(Dir::Left, height, depth),
+                face(Dir::Right, height, depth),
+                face(Dir::Top, width, depth),
+                face(Dir::Bottom, width, depth),
+            ],
+            label,
+        }
+    }
+
+    // Search the puzzle set up by `plain`, then by `pruned`: the same solutions are found
+    // in the same order, in `nodes` (plain, then pruned), the pruning rejecting `rejections`
+    // placements as `rejected` tells. Returns the solutions.
+    fn assert_prunes(
+        puzzle: &Puzzle,
+        plain: impl Fn(&mut Solver),
+        pruned: impl Fn(&mut Solver),
+        rejected: impl Fn(Rejection) -> bool,
+        nodes: (u64, u64),
+        rejections: u64,
+    ) -> Vec<Solution> {
+        let search = |set_up: &dyn Fn(&mut Solver)| {
+            let mut solver = puzzle.solver();
+            set_up(&mut solver);
+            solver.enable_stats();
+            solver.init();
+            (solver.by_ref().collect_vec(), solver)
+        };
+        let (expected, plain) = search(&plain);
+        let (solutions, solver) = search(&pruned);
+        assert_eq!(solutions, expected);
+        assert_eq!(
+            solver
+                .stats()
+                .unwrap()
+                .rejections()
+                .iter()
+                .filter(|&&(rejection, _)| rejected(rejection))
+                .map(|(_, count)| count)
+                .sum::<u64>(),
+            rejections
+        );
+        assert_eq!((plain.progress().nodes, solver.progress().nodes), nodes);
+        solutions
+    }
+
+    // four 2x1x1 blocks with face values, any orientation, to fill a 2x2x2 box to a sum of 10
+    fn rot_blocks_4x_2x1x1_to_sum() -> Vec<Vec<Block>> {
+        [
+            ("A", [5, 3, 4, 5, 2, 5]),
+            ("B", [5, 2, 5, 5, 6, 4]),
+            ("C", [6, 3, 2, 1, 2, 5]),
+            ("D", [2, 2, 4, 4, 2, 6]),
+        ]
+        .iter()
+        .map(|&(label, values)| {
+            // by `Dir` index: the four long faces, then the two ends
+            let faces = [
+                Dir::Front,
+                Dir::Back,
+                Dir::Left,
+                Dir::Right,
+                Dir::Top,
+                Dir::Bottom,
+            ]
+            .map(|dir| Face {
+                value: values[dir as usize],
+                long: if (dir as usize) < 4 { 2 } else { 1 },
+                short: 1,
+                block: 0,
+                dir,
+            });
+            all_block_rots(&Block {
+                height: 2,
+                width: 1,
+                depth: 1,
+                faces,
+                label,
+            })
+        })
+        .collect_vec()
+    }
+
+    // two 2x1x1 blocks with face values, any orientation, to fill a 2x2x1 box to a sum of 12
+    pub(crate) fn rot_blocks_2x_2x1x1_to_sum() -> Vec<Vec<Block>> {
+        blocks_2x_2x1x1_to_sum()
+            .iter()
+            .map(all_block_rots)
+            .collect_vec()
+    }
+
+    pub(crate) fn blocks_2x_2x1x1_to_sum() -> [Block; 2] {
+        let block_a = Block {
+            height: 2,
+            width: 1,
+            depth: 1,
+            faces: [
+                Face {
+                    value: 9,
+                    long: 2,
+                    short: 1,
+                    block: 0,
+                    dir: Dir::Front,
+                },
+                Face {
+                    value: 8,
+                    long: 2,
+                    short: 1,
+                    block: 0,
+                    dir: Dir::Back,
+                },
+                Face {
+                    value: 12,
+                    long: 2,
+                    short: 1,
+                    block: 0,
+                    dir: Dir::Left,
+                },
+                Face {
+                    value: 14,
+                    long: 2,
+                    short: 1,
+                    block: 0,
+                    dir: Dir::Right,
+                },
+                Face {
+                    value: 6,
+                    long: 1,
+                    short: 1,
+                    block: 0,
+                    dir: Dir::Top,
+                },
+                Face {
+                    value: 7,
+                    long: 1,
+                    short: 1,
+                    block: 0,
+                    dir: Dir::Bottom,
+                },
+            ],
+            label: "A",
+        };
+
+        let block_b = Block {
+            height: 2,
+            width: 1,
+            depth: 1,
+            faces: [
+                Face {
+                    value: 3,
+                    long: 2,
+                    short: 1,
+                    block: 0,
+                    dir: Dir::Front,
+                },
+                Face {
+                    value: 4,
+                    long: 2,
+                    short: 1,
+                    block: 0,
+                    dir: Dir::Back,
+                },
+                Face {
+                    value: 13,
+                    long: 2,
+                    short: 1,
+                    block: 0,
+                    dir: Dir::Left,
+                },
+                Face {
+                    value: 12,
+                    long: 2,
+                    short: 1,
+                    block: 0,
+                    dir: Dir::Right,
+                },
+                Face {
+                    value: 6,
+                    long: 1,
+                    short: 1,
+                    block: 0,
+                    dir: Dir::Top,
+                },
+                Face {
+                    value: 5,
+                    long: 1,
+                    short: 1,
+                    block: 0,
+                    dir: Dir::Bottom,
+                },
+            ],
+            label: "B",
+        };
+
+        [block_a, block_b]
+    }
+
+    const TEST_FACE_1X1: Face = Face {
+        value: 1,
+        long: 1,
+        short: 1,
+        block: 1,
+        dir: Dir::Front,
+    };
+
+    const TEST_FACE_2X1: Face = Face {
+        value: 2,
+        long: 2,
+        short: 1,
+        block: 2,
+        dir: Dir::Front,
+    };
+
+    const TEST_BLOCK_2X1X1: Block = Block {
+        height: 2,
+        width: 1,
+        depth: 1,
+        faces: [
+            TEST_FACE_2X1,
+            TEST_FACE_2X1,
+            TEST_FACE_2X1,
+            TEST_FACE_2X1,
+            TEST_FACE_1X1,
+            TEST_FACE_1X1,
+        ],
+        label: "2X1X1",
+    };
+
+    #[test]
+    fn solve_2x_2x1x1_in_2x2x1() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let mut block_a = TEST_BLOCK_2X1X1.clone();
+        block_a.label = "A";
+        let block_a_rot_1 = rot_block(&Dir::Front, &block_a);
+        let block_a_rot_2 = rot_block(&Dir::Right, &block_a);
+
+        let mut block_b = TEST_BLOCK_2X1X1.clone();
+        block_b.label = "B";
+        let block_b_rot_1 = rot_block(&Dir::Front, &block_b);
+        let block_b_rot_2 = rot_block(&Dir::Right, &block_b);
+
+        let rot_blocks = [
+            [block_a, block_a_rot_1, block_a_rot_2].to_vec(),
+            [block_b, block_b_rot_1, block_b_rot_2].to_vec(),
+        ]
+        .to_vec();
+
+        let mut solver = Solver::new(2, 2, 1, None, rot_blocks);
+        solver.init();
+        while !solver.done() && solver.step() {}
+        assert_eq!(solver.solutions.len(), 4);
+    }
+
+    #[test]
+    fn solve_4x_2x1x1_in_2x2x2() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let mut block_a = TEST_BLOCK_2X1X1.clone();
+        block_a.label = "A";
+        let block_a_rot_1 = rot_block(&Dir::Front, &block_a);
+        let block_a_rot_2 = rot_block(&Dir::Right, &block_a);
+
+        let mut block_b = TEST_BLOCK_2X1X1.clone();
+        block_b.label = "B";
+        let block_b_rot_1 = rot_block(&Dir::Front, &block_b);
+        let block_b_rot_2 = rot_block(&Dir::Right, &block_b);
+
+        let mut block_c = TEST_BLOCK_2X1X1.clone();
+        block_c.label = "C";
+        let block_c_rot_1 = rot_block(&Dir::Front, &block_c);
+        let block_c_rot_2 = rot_block(&Dir::Right, &block_c);
+
+        let mut block_d = TEST_BLOCK_2X1X1.clone();
+        block_d.label = "D";
+        let block_d_rot_1 = rot_block(&Dir::Front, &block_d);
+        let block_d_rot_2 = rot_block(&Dir::Right, &block_d);
+
+        let rot_blocks = [
+            [block_a, block_a_rot_1, block_a_rot_2].to_vec(),
+            [block_b, block_b_rot_1, block_b_rot_2].to_vec(),
+            [block_c, block_c_rot_1, block_c_rot_2].to_vec(),
+            [block_d, block_d_rot_1, block_d_rot_2].to_vec(),
+        ]
+        .to_vec();
+
+        let mut solver = Solver::new(2, 2, 2, None, rot_blocks);
+        solver.init();
+        while !solver.done() && solver.step() {}
+        assert_eq!(solver.solutions.len(), 216);
+    }
+
+    #[test]
+    fn iterate_4x_2x1x1_in_2x2x2() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let mut solver = Solver::new(2, 2, 2, None, rot_blocks_4x_2x1x1());
+        solver.set_retention(Retention::Sample(10));
+        solver.init();
+        let solutions: HashSet<Solution> = solver.by_ref().collect();
+        assert_eq!(solutions.len(), 216);
+        assert!(solutions.iter().all(|s| s.placements().len() == 4));
+        // kept as the retention policy says, as well as handed over
+        assert_eq!(solver.stored_solutions().len(), 10);
+        assert!(solver
+            .stored_solutions()
+            .iter()
+            .all(|s| solutions.contains(s)));
+        assert_eq!(solver.solution_count(), 216);
+        assert!(solver.done());
+        // fused, rather than started over
+        assert_eq!(solver.next(), None);
+        assert_eq!(solver.next(), None);
+        assert_eq!(solver.solution_count(), 216);
+    }
+
+    #[test]
+    fn count_4x_2x1x1_in_2x2x2_twice() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let mut solver = Solver::new(2, 2, 2, None, rot_blocks_4x_2x1x1());
+        solver.init();
+        assert_eq!(solver.count_solutions(), 216);
+        assert_eq!(solver.count_solutions(), 216);
+        assert!(solver.stored_solutions().is_empty());
+    }
+
+    #[test]
+    fn count_4x_2x1x1_in_2x2x2_keeping_a_sample() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let mut solver = Solver::new(2, 2, 2, None, rot_blocks_4x_2x1x1());
+        solver.set_retention(Retention::Sample(10));
+        solver.init();
+        while solver.step() {}
+        assert_eq!(solver.stored_solutions().len(), 10);
+        assert_eq!(solver.solution_count(), 216);
+    }
+
+    #[test]
+    fn stop_4x_2x1x1_in_2x2x2_after_3_solutions() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let rot_blocks = rot_blocks_4x_2x1x1();
+
+        let mut solver = Solver::new(2, 2, 2, None, rot_blocks.clone());
+        solver.set_max_solutions(Some(3));
+        solver.init();
+        assert_eq!(solver.by_ref().count(), 3);
+        assert!(solver.done());
+        assert_eq!(solver.solution_count(), 3);
+
+        // going on past the limit
+        solver.set_max_solutions(Some(5));
+        assert_eq!(solver.by_ref().count(), 2);
+        assert_eq!(solver.solution_count(), 5);
+
+        let mut solver = Solver::new(2, 2, 2, None, rot_blocks.clone());
+        solver.init();
+        assert!(!solver.is_unique());
+        assert_eq!(solver.solution_count(), 2);
+        // the search goes on without a limit, as before the check
+        assert_eq!(solver.count_solutions(), 216);
+        assert!(!solver.is_unique());
+        assert_eq!(solver.solution_count(), 216);
+
+        // found past a limit of one solution
+        let mut solver = Solver::new(2, 2, 2, None, rot_blocks);
+        solver.set_max_solutions(Some(1));
+        solver.init();
+        assert_eq!(solver.by_ref().count(), 1);
+        assert!(!solver.is_unique());
+        assert_eq!(solver.solution_count(), 2);
+        assert!(solver.done());
+    }
+
+    #[test]
+    fn ndjson_sink_writes_a_line_per_solution() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let path = std::env::temp_dir().join(format!("ndjson_sink_{}.ndjson", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut solver = Solver::new(2, 2, 2, None, rot_blocks_4x_2x1x1());
+        solver.add_sink(Box::new(crate::sink::NdjsonSink::append(&path).unwrap()));
+        solver.init();
+        let first = solver.next().unwrap();
+        while solver.step() {}
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let records = content
+            .lines()
+            .map(|l| serde_json::from_str::<crate::sink::SolutionRecord>(l).unwrap())
+            .collect_vec();
+        assert_eq!(records.len(), 216);
+        assert_eq!(records[0].index, 1);
+        assert_eq!(
+            records[0]
+                .blocks
+                .iter()
+                .map(|b| (b.block_id, b.rot_id, b.position))
+                .collect_vec(),
+            first
+                .placements()
+                .iter()
+                .map(|bip| (bip.block_id, bip.rot_id, bip.position))
+                .collect_vec()
+        );
+    }
+
+    #[test]
+    fn observe_4x_2x1x1_in_2x2x2() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        #[derive(Default)]
+        struct Counts {
+            placed: usize,
+            removed: usize,
+            overlaps: usize,
+            solutions: usize,
+        }
+        struct Counter(std::sync::Arc<std::sync::Mutex<Counts>>);
+        impl SearchObserver for Counter {
+            fn block_placed(&mut self, _: &BlockInPuzzle, _: usize) {
+                self.0.lock().unwrap().placed += 1;
+            }
+            fn block_removed(&mut self, _: &BlockInPuzzle, _: usize) {
+                self.0.lock().unwrap().removed += 1;
+            }
+            fn placement_rejected(&mut self, _: &BlockInPuzzle, rejection: Rejection) {
+                if rejection == Rejection::Overlap {
+                    self.0.lock().unwrap().overlaps += 1;
+                }
+            }
+            fn solution_found(&mut self, _: &Solution) {
+                self.0.lock().unwrap().solutions += 1;
+            }
+        }
+
+        let counts = std::sync::Arc::new(std::sync::Mutex::new(Counts::default()));
+        let mut solver = Solver::new(2, 2, 2, None, rot_blocks_4x_2x1x1());
+        solver.add_observer(Box::new(Counter(counts.clone())));
+        solver.init();
+        while solver.step() {}
+        let counts = counts.lock().unwrap();
+        assert_eq!(counts.solutions, 216);
+        // the search ends with all blocks removed
+        assert_eq!(counts.placed, counts.removed);
+        assert!(counts.overlaps > 0);
+    }
+
+    #[test]
+    fn stats_4x_2x1x1_in_2x2x2() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let mut solver = Solver::new(2, 2, 2, None, rot_blocks_4x_2x1x1());
+        solver.enable_stats();
+        solver.init();
+        while solver.step() {}
+        let stats = solver.stats().unwrap();
+        assert_eq!(stats.solutions, 216);
+        assert_eq!(stats.max_depth, 4);
+        assert_eq!(
+            stats.nodes + stats.rejections().iter().map(|r| r.1).sum::<u64>(),
+            stats.attempts
+        );
+        assert_eq!(stats.backtracks.iter().sum::<u64>(), stats.nodes);
+        assert!(stats.rejections_by_side().is_empty());
+    }
+
+    #[test]
+    fn parallel_4x_2x1x1_in_2x2x2_matches_sequential() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        // the solutions handed to an observer, in order
+        struct Recorder(std::sync::Arc<std::sync::Mutex<Vec<Solution>>>);
+        impl SearchObserver for Recorder {
+            fn solution_found(&mut self, solution: &Solution) {
+                self.0.lock().unwrap().push(solution.clone());
+            }
+        }
+
+        let mut sequential = Solver::new(2, 2, 2, None, rot_blocks_4x_2x1x1());
+        let mut parallel = sequential.puzzle().solver();
+        sequential.enable_stats();
+        sequential.init();
+        while sequential.step() {}
+
+        for split_depth in 0..=4 {
+            let mut solver = parallel.puzzle().solver();
+            solver.enable_stats();
+            // already started, as the search is run over from scratch
+            solver.init();
+            crate::parallel::solve(&mut solver, split_depth, 3);
+            assert!(solver.done());
+            assert_eq!(solver.solution_count(), 216);
+            assert_eq!(solver.stored_solutions(), sequential.stored_solutions());
+            // the nodes above the subtrees are counted once
+            assert_eq!(solver.progress().nodes, sequential.progress().nodes);
+            assert_eq!(
+                solver.stats().unwrap().nodes,
+                sequential.stats().unwrap().nodes
+            );
+
+            // streamed to the observer as found, in the order of the sequential search
+            let recorded = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+            let mut solver = parallel.puzzle().solver();
+            solver.add_observer(Box::new(Recorder(recorded.clone())));
+            solver.set_max_solutions(Some(150));
+            crate::parallel::solve(&mut solver, split_depth, 3);
+            assert_eq!(solver.solution_count(), 150);
+            assert_eq!(
+                *recorded.lock().unwrap(),
+                &sequential.stored_solutions()[..150]
+            );
+            assert_eq!(
+                solver.stored_solutions(),
+                &sequential.stored_solutions()[..150]
+            );
+        }
+
+        parallel.set_max_solutions(Some(100));
+        crate::parallel::solve(&mut parallel, 2, 3);
+        assert_eq!(parallel.solution_count(), 100);
+        assert_eq!(
+            parallel.stored_solutions(),
+            &sequential.stored_solutions()[..100]
+        );
+    }
+
+    #[test]
+    fn resume_4x_2x1x1_in_2x2x2_from_checkpoint() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let mut uninterrupted = Solver::new(2, 2, 2, None, rot_blocks_4x_2x1x1());
+        let puzzle = uninterrupted.puzzle();
+        uninterrupted.set_retention(Retention::Sample(50));
+        uninterrupted.enable_stats();
+        uninterrupted.init();
+        while uninterrupted.step() {}
+
+        let path = std::env::temp_dir().join(format!("checkpoint_{}.json", std::process::id()));
+        for steps in [0, 1, 7, 60, 1000] {
+            let mut solver = puzzle.solver();
+            solver.set_retention(Retention::Sample(50));
+            solver.enable_stats();
+            solver.init();
+            for _ in 0..steps {
+                if !solver.step() {
+                    break;
+                }
+            }
+            solver.checkpoint(&path).unwrap();
+            let checkpoint = serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+            let mut resumed = puzzle.restore(checkpoint).unwrap();
+            assert_eq!(resumed.stack, solver.stack);
+            assert_eq!(resumed.occupied, solver.occupied);
+            assert_eq!(resumed.rem, solver.rem);
+            assert_eq!(resumed.solution_count(), solver.solution_count());
+            assert_eq!(resumed.done(), solver.done());
+            while resumed.step() {}
+            assert_eq!(resumed.solution_count(), 216);
+            assert_eq!(resumed.stored_solutions(), uninterrupted.stored_solutions());
+            assert_eq!(
+                resumed.stats().unwrap().nodes,
+                uninterrupted.stats().unwrap().nodes
+            );
+        }
+
+        // a checkpoint only resumes into the same puzzle
+        let other = Solver::new(2, 2, 1, None, rot_blocks_4x_2x1x1()[..2].to_vec()).puzzle();
+        let checkpoint = serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(other.restore(checkpoint).is_err());
+    }
+
+    #[test]
+    fn bounded_steps_4x_2x1x1_in_2x2x2() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let mut solver = Solver::new(2, 2, 2, None, rot_blocks_4x_2x1x1());
+        solver.init();
+        assert_eq!(solver.step_n(10), StepOutcome::Paused);
+        assert_eq!(solver.step_to_solution_n(1000), StepOutcome::Solved);
+        assert!(solver.solved());
+        assert_eq!(
+            solver.step_for(Duration::from_secs(60)),
+            StepOutcome::Finished
+        );
+        assert_eq!(solver.solution_count(), 216);
+        assert_eq!(solver.step_n(0), StepOutcome::Finished);
+        assert_eq!(solver.step_n(10), StepOutcome::Finished);
+        assert_eq!(solver.step_to_solution_n(10), StepOutcome::Finished);
+        assert_eq!(solver.step_for(Duration::ZERO), StepOutcome::Finished);
+        assert_eq!(solver.solution_count(), 216);
+
+        let mut solver = Solver::new(2, 2, 2, None, rot_blocks_4x_2x1x1());
+        solver.init();
+        let token = solver.cancel_token();
+        std::thread::spawn(move || token.cancel()).join().unwrap();
+        assert_eq!(solver.step_n(1000), StepOutcome::Cancelled);
+        assert_eq!(solver.solution_count(), 0);
+    }
+
+    #[test]
+    fn dead_space_pruning_5x_mixed_in_4x4x5() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let solutions = assert_prunes(
+            &Solver::new(4, 4, 5, None, rot_blocks_5x_mixed()).puzzle(),
+            |_| {},
+            |solver| solver.set_dead_space_pruning(true),
+            |rejection| rejection == Rejection::DeadSpace,
+            (2531, 2483),
+            48,
+        );
+        assert_eq!(solutions.len(), 352);
+    }
+
+    #[test]
+    fn side_sum_pruning_4x_2x1x1_in_2x2x2_to_sum() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let puzzle = Puzzle::new(
+            2,
+            2,
+            2,
+            Some(10),
+            rot_blocks_4x_2x1x1_to_sum(),
+            Symmetry::None,
+        );
+        let solutions = assert_prunes(
+            &puzzle,
+            |solver| solver.set_side_sum_pruning(false),
+            |_| {},
+            |rejection| matches!(rejection, Rejection::SideSumUnreachable(_)),
+            (1374, 200),
+            1053,
+        );
+        assert_eq!(solutions.len(), 24);
+
+        let mut exact_cover = puzzle.solver();
+        exact_cover.set_side_sum_pruning(false);
+        exact_cover.run(Backend::ExactCover);
+        assert_eq!(
+            exact_cover
+                .stored_solutions()
+                .iter()
+                .collect::<HashSet<_>>(),
+            solutions.iter().collect::<HashSet<_>>()
+        );
+    }
+
+    #[test]
+    fn side_tiling_pruning_5x_mixed_in_4x4x5() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        assert_prunes(
+            &Solver::new(4, 4, 5, None, rot_blocks_5x_mixed()).puzzle(),
+            |_| {},
+            |solver| solver.set_side_tiling_pruning(true),
+            |rejection| matches!(rejection, Rejection::SideUntileable(_)),
+            (2531, 977),
+            558,
+        );
+    }
+
+    #[test]
+    fn dead_end_cache_5x_mixed_in_4x4x5() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let puzzle = Solver::new(4, 4, 5, None, rot_blocks_5x_mixed()).puzzle();
+        let expected = assert_prunes(
+            &puzzle,
+            |_| {},
+            |solver| solver.set_dead_end_cache(Some(1 << 12)),
+            |rejection| rejection == Rejection::KnownDeadEnd,
+            (2531, 1382),
+            653,
+        );
+        // a single slot keeps replacing its dead end
+        let mut solver = puzzle.solver();
+        solver.set_dead_end_cache(Some(1));
+        solver.init();
+        assert_eq!(solver.by_ref().collect_vec(), expected);
+
+        // the packings rejected as symmetric still complete their states
+        for symmetry in [Symmetry::Rotations, Symmetry::RotationsAndMirrors] {
+            let solutions = |capacity| {
+                let mut solver =
+                    Puzzle::new(4, 4, 5, None, rot_blocks_5x_mixed(), symmetry).solver();
+                solver.set_dead_end_cache(capacity);
+                solver.init();
+                solver.run(Backend::Backtracking);
+                solver.stored_solutions().to_vec()
+            };
+            assert_eq!(solutions(Some(1 << 12)), solutions(None));
+        }
+    }
+
+    #[test]
+    fn symmetric_packings_4x_2x1x1_in_2x2x2() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let count = |symmetry, backend| {
+            let mut solver = Puzzle::new(2, 2, 2, None, rot_blocks_4x_2x1x1(), symmetry).solver();
+            solver.set_retention(Retention::Nothing);
+            solver.init();
+            solver.run(backend);
+            solver.solution_count()
+        };
+        for backend in [
+            Backend::Backtracking,
+            Backend::ExactCover,
+            Backend::MostConstrained,
+        ] {
+            assert_eq!(count(Symmetry::None, backend), 216);
+            // no packing of 4 distinct blocks is its own rotation: 216 / 24
+            assert_eq!(count(Symmetry::Rotations, backend), 9);
+            // the 24 packings of 4 parallel blocks are their own mirror images
+            // across the plane across them: (216 + 3 * 24) / 48
+            assert_eq!(count(Symmetry::RotationsAndMirrors, backend), 6);
+        }
     }
 
-    result.push_str("Bottom:\n");
-    for k in 0..puzzle_depth {
-        for j in 0..puzzle_width {
-            let idx = k * slice_area + j * puzzle_height;
-            result.push_str(&format!(
-                "{} ",
-                state[idx]
-                    .map(|b| format!(
-                        "{:0>2}",
-                        rot_blocks[b.block_id][b.rot_id].faces[Dir::Bottom as usize].value
-                    ))
-                    .unwrap_or("".to_string())
-            ));
+    #[test]
+    fn symmetry_cuts_the_puzzle_search() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let nodes = |shape_only, symmetry| {
+            let mut solver = solver_with_symmetry(shape_only, symmetry);
+            solver.set_retention(Retention::Nothing);
+            solver.run(Backend::Backtracking);
+            (solver.solution_count(), solver.progress().nodes)
+        };
+        for (shape_only, solutions, bound) in [(false, 4, 14177), (true, 1074, 209766)] {
+            let (count, cut) = nodes(shape_only, Symmetry::Rotations);
+            let (_, full) = nodes(shape_only, Symmetry::None);
+            assert_eq!(count, solutions);
+            // the cut never grows the search, and keeps it within what it was measured at
+            assert!(cut <= full, "{} nodes up from {}", cut, full);
+            assert!(cut <= bound, "{} nodes over {}", cut, bound);
         }
-        result.push('\n');
     }
 
-    result
-}
-#[cfg(test)]
-mod test {
-    use super::*;
+    #[test]
+    fn orbits_4x_2x1x1_in_2x2x2() {
+        let _ = env_logger::builder().is_test(true).try_init();
 
-    const TEST_FACE_1X1: Face = Face {
-        value: 1,
-        long: 1,
-        short: 1,
-        block: 1,
-        dir: Dir::Front,
-    };
+        let mut solver = Solver::new(2, 2, 2, None, rot_blocks_4x_2x1x1());
+        solver.init();
+        while solver.step() {}
+        assert_eq!(solver.orbits(Symmetry::None).len(), 216);
+        let orbits = solver.orbits(Symmetry::Rotations);
+        assert_eq!(orbits.len(), 9);
+        assert!(orbits.iter().all(|orbit| orbit.size == 24));
+        let orbits = solver.orbits(Symmetry::RotationsAndMirrors);
+        assert_eq!(orbits.len(), 6);
+        assert_eq!(orbits.iter().map(|orbit| orbit.size).sum::<usize>(), 216);
 
-    const TEST_FACE_2X1: Face = Face {
-        value: 2,
-        long: 2,
-        short: 1,
-        block: 2,
-        dir: Dir::Front,
-    };
+        // the solutions of a search which discounts rotations are their own orbit
+        let mut solver =
+            Puzzle::new(2, 2, 2, None, rot_blocks_4x_2x1x1(), Symmetry::Rotations).solver();
+        solver.init();
+        while solver.step() {}
+        let orbits = solver.orbits(Symmetry::Rotations);
+        assert_eq!(orbits.len(), 9);
+        for (orbit, solution) in orbits.iter().zip(solver.stored_solutions()) {
+            assert_eq!(
+                solver.canonical_form(&orbit.solution, Symmetry::Rotations),
+                *orbit
+            );
+            assert_eq!(solver.canonical_form(solution, Symmetry::Rotations), *orbit);
+        }
+    }
 
-    const TEST_BLOCK_2X1X1: Block = Block {
-        height: 2,
-        width: 1,
-        depth: 1,
-        faces: [
-            TEST_FACE_2X1,
-            TEST_FACE_2X1,
-            TEST_FACE_2X1,
-            TEST_FACE_2X1,
-            TEST_FACE_1X1,
-            TEST_FACE_1X1,
-        ],
-        label: "2X1X1",
-    };
+    #[test]
+    fn rot_multiplicities() {
+        let shapes = puzzle(true, Symmetry::None).solver();
+        let blocks = puzzle(false, Symmetry::None).solver();
+        for (block_id, block) in crate::common::BLOCKS.iter().enumerate() {
+            let square = block.height == block.width || block.width == block.depth;
+            assert_eq!(
+                shapes.rot_blocks[block_id].len(),
+                if square { 3 } else { 6 }
+            );
+            for rot_id in 0..shapes.rot_blocks[block_id].len() {
+                assert_eq!(
+                    shapes.rot_multiplicity(block_id, rot_id),
+                    if square { 8 } else { 4 }
+                );
+            }
+            // the values of the faces tell all the rotations apart
+            assert_eq!(blocks.rot_blocks[block_id].len(), 24);
+            assert_eq!(blocks.rot_multiplicity(block_id, 0), 1);
+        }
+
+        let mut solver = Solver::new(2, 2, 2, None, rot_blocks_4x_2x1x1());
+        solver.init();
+        while solver.step() {}
+        assert_eq!(solver.expanded_solution_count(), 216 * 8u128.pow(4));
+        let solution = &solver.stored_solutions()[0];
+        assert_eq!(solver.multiplicity(solution), 8u128.pow(4));
+    }
 
     #[test]
-    fn solve_2x_2x1x1_in_2x2x1() {
+    fn shape_rots_stand_for_all_rotations_in_3x2x2() {
         let _ = env_logger::builder().is_test(true).try_init();
 
-        let mut block_a = TEST_BLOCK_2X1X1.clone();
-        block_a.label = "A";
-        let block_a_rot_1 = rot_block(&Dir::Front, &block_a);
-        let block_a_rot_2 = rot_block(&Dir::Right, &block_a);
+        // a flat block turning 4 ways into each shape, two rods turning 8 ways
+        let blocks = [
+            blank_block("A", 3, 2, 1),
+            blank_block("B", 3, 1, 1),
+            blank_block("C", 3, 1, 1),
+        ];
+        let search = |rot_blocks: Vec<Vec<Block>>| {
+            let mut solver = Solver::new(3, 2, 2, None, rot_blocks);
+            solver.set_retention(Retention::Nothing);
+            solver.init();
+            solver.run(Backend::Backtracking);
+            solver
+        };
+        let shapes = search(blocks.iter().map(all_shape_rots).collect_vec());
+        let all = search(blocks.iter().map(all_rots).collect_vec());
+        assert_eq!((shapes.solution_count(), shapes.progress().nodes), (8, 20));
+        assert_eq!((all.solution_count(), all.progress().nodes), (2048, 2392));
+        assert_eq!(shapes.expanded_solution_count(), 8 * 4 * 8 * 8);
+        assert_eq!(shapes.expanded_solution_count(), all.solution_count());
+    }
 
-        let mut block_b = TEST_BLOCK_2X1X1.clone();
-        block_b.label = "B";
-        let block_b_rot_1 = rot_block(&Dir::Front, &block_b);
-        let block_b_rot_2 = rot_block(&Dir::Right, &block_b);
+    #[test]
+    fn cell_orders_4x_2x1x1_in_3x2x2() {
+        let _ = env_logger::builder().is_test(true).try_init();
 
-        let rot_blocks = [
-            [block_a, block_a_rot_1, block_a_rot_2].to_vec(),
-            [block_b, block_b_rot_1, block_b_rot_2].to_vec(),
-        ]
-        .to_vec();
+        let mut blocks = rot_blocks_4x_2x1x1();
+        blocks.extend(rot_blocks_4x_2x1x1().into_iter().take(2));
+        let solutions = |order| {
+            let puzzle = Puzzle::in_order(
+                [3, 2, 2],
+                None,
+                blocks.clone(),
+                Symmetry::None,
+                order,
+                BlockOrder::default(),
+            );
+            let mut solver = puzzle.solver();
+            solver.init();
+            while solver.step() {}
+            solver
+                .stored_solutions()
+                .iter()
+                .cloned()
+                .collect::<HashSet<_>>()
+        };
+        let expected = solutions(CellOrder::default());
+        assert!(!expected.is_empty());
+        for axes in (0..3).permutations(3) {
+            let order = CellOrder {
+                axes: [axes[0], axes[1], axes[2]],
+                reversed: [true, false, axes[0] == 1],
+            };
+            assert_eq!(solutions(order), expected, "{:?}", order);
+        }
+    }
 
-        let mut solver = Solver {
-            puzzle_height: 2,
-            puzzle_width: 2,
-            puzzle_depth: 1,
-            target: None,
-            rot_blocks,
-            stack: vec![],
-            rem: HashSet::from_iter(0..2),
-            position: 0,
-            state: vec![None; 4],
-            face_sums: [0; 6],
-            face_free_areas: [4, 4, 2, 2, 2, 2],
-            done: false,
-            solutions: HashSet::new(),
+    #[test]
+    fn block_orders_4x_2x1x1_in_3x2x2() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let mut blocks = rot_blocks_4x_2x1x1();
+        blocks.extend(rot_blocks_4x_2x1x1().into_iter().take(2));
+        let solver = |block_order| {
+            let puzzle = Puzzle::in_order(
+                [3, 2, 2],
+                None,
+                blocks.clone(),
+                Symmetry::None,
+                CellOrder::default(),
+                block_order,
+            );
+            let mut solver = puzzle.solver();
+            solver.init();
+            solver
+        };
+        let solutions = |block_order| {
+            let mut solver = solver(block_order);
+            while solver.step() {}
+            solver
+                .stored_solutions()
+                .iter()
+                .cloned()
+                .collect::<HashSet<_>>()
         };
+        let expected = solutions(BlockOrder::Id);
+        for block_order in [
+            BlockOrder::LargestVolume,
+            BlockOrder::MostSideFaces,
+            BlockOrder::FewestPlacements,
+            BlockOrder::Random(7),
+        ] {
+            assert_eq!(solutions(block_order), expected, "{:?}", block_order);
+        }
+        // the first solution depends on the seed
+        let firsts = (0..8)
+            .map(|seed| solver(BlockOrder::Random(seed)).next().unwrap())
+            .collect::<HashSet<_>>();
+        assert!(firsts.len() > 1);
+    }
+
+    #[test]
+    fn progress_4x_2x1x1_in_2x2x2() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let mut solver = Solver::new(2, 2, 2, None, rot_blocks_4x_2x1x1());
+        let nodes = solver.estimate_nodes(200, 7);
         solver.init();
-        while !solver.done() && solver.step() {}
-        assert_eq!(solver.solutions.len(), 4);
+        let mut fraction = solver.progress().fraction;
+        assert_eq!(fraction, 0.);
+        let mut fractions = vec![];
+        while solver.step() {
+            let progress = solver.progress();
+            assert!(progress.fraction >= fraction && progress.fraction < 1.);
+            assert!(progress.of_estimate(nodes).fraction < 1.);
+            fraction = progress.fraction;
+            fractions.push((progress.nodes, fraction));
+        }
+        let progress = solver.progress();
+        // the tree is regular: the explored fraction goes along with the nodes explored
+        for (nodes, fraction) in fractions {
+            let explored = nodes as f64 / progress.nodes as f64;
+            assert!(
+                (fraction - explored).abs() < 0.1,
+                "{} vs {}",
+                fraction,
+                explored
+            );
+        }
+        assert_eq!(progress.fraction, 1.);
+        assert_eq!(progress.of_estimate(nodes).fraction, 1.);
+        assert_eq!(
+            progress.remaining(Duration::from_secs(10)),
+            Some(Duration::ZERO)
+        );
+        // the tree is small and regular enough for the estimate to be close
+        let actual = progress.nodes as f64;
+        assert!(
+            nodes > actual * 0.8 && nodes < actual * 1.25,
+            "{} vs {}",
+            nodes,
+            actual
+        );
     }
 
     #[test]
-    fn solve_4x_2x1x1_in_2x2x2() {
+    fn exact_cover_4x_2x1x1_in_2x2x2_matches_backtracking() {
         let _ = env_logger::builder().is_test(true).try_init();
 
-        let mut block_a = TEST_BLOCK_2X1X1.clone();
-        block_a.label = "A";
-        let block_a_rot_1 = rot_block(&Dir::Front, &block_a);
-        let block_a_rot_2 = rot_block(&Dir::Right, &block_a);
+        let mut backtracking = Solver::new(2, 2, 2, None, rot_blocks_4x_2x1x1());
+        let puzzle = backtracking.puzzle();
+        backtracking.run(Backend::Backtracking);
+        let mut exact_cover = puzzle.solver();
+        exact_cover.run(Backend::ExactCover);
+        assert!(exact_cover.done());
+        assert_eq!(exact_cover.solution_count(), 216);
+        assert_eq!(
+            exact_cover
+                .stored_solutions()
+                .iter()
+                .collect::<HashSet<_>>(),
+            backtracking
+                .stored_solutions()
+                .iter()
+                .collect::<HashSet<_>>()
+        );
 
-        let mut block_b = TEST_BLOCK_2X1X1.clone();
-        block_b.label = "B";
-        let block_b_rot_1 = rot_block(&Dir::Front, &block_b);
-        let block_b_rot_2 = rot_block(&Dir::Right, &block_b);
+        // below a subproblem, with a limit
+        let prefix = &backtracking.stored_solutions()[100].placements[..2];
+        let mut sub_backtracking = puzzle.subproblem(prefix).unwrap();
+        sub_backtracking.run(Backend::Backtracking);
+        let mut sub_exact_cover = puzzle.subproblem(prefix).unwrap();
+        sub_exact_cover.run(Backend::ExactCover);
+        assert!(sub_exact_cover.solution_count() > 0);
+        assert_eq!(
+            sub_exact_cover.solution_count(),
+            sub_backtracking.solution_count()
+        );
+        let mut limited = puzzle.solver();
+        limited.set_max_solutions(Some(10));
+        limited.run(Backend::ExactCover);
+        assert_eq!(limited.solution_count(), 10);
+    }
 
-        let mut block_c = TEST_BLOCK_2X1X1.clone();
-        block_c.label = "C";
-        let block_c_rot_1 = rot_block(&Dir::Front, &block_c);
-        let block_c_rot_2 = rot_block(&Dir::Right, &block_c);
+    #[test]
+    fn most_constrained_matches_backtracking() {
+        let _ = env_logger::builder().is_test(true).try_init();
 
-        let mut block_d = TEST_BLOCK_2X1X1.clone();
-        block_d.label = "D";
-        let block_d_rot_1 = rot_block(&Dir::Front, &block_d);
-        let block_d_rot_2 = rot_block(&Dir::Right, &block_d);
+        let solutions = |puzzle: &Puzzle, backend| {
+            let mut solver = puzzle.solver();
+            solver.init();
+            solver.run(backend);
+            assert!(solver.done());
+            solver
+                .stored_solutions()
+                .iter()
+                .cloned()
+                .collect::<HashSet<_>>()
+        };
+        for puzzle in [
+            Solver::new(2, 2, 2, None, rot_blocks_4x_2x1x1()).puzzle(),
+            Puzzle::new(
+                2,
+                2,
+                1,
+                Some(12),
+                rot_blocks_2x_2x1x1_to_sum(),
+                Symmetry::None,
+            ),
+        ] {
+            let expected = solutions(&puzzle, Backend::Backtracking);
+            assert!(!expected.is_empty());
+            assert_eq!(solutions(&puzzle, Backend::MostConstrained), expected);
+        }
 
-        let rot_blocks = [
-            [block_a, block_a_rot_1, block_a_rot_2].to_vec(),
-            [block_b, block_b_rot_1, block_b_rot_2].to_vec(),
-            [block_c, block_c_rot_1, block_c_rot_2].to_vec(),
-            [block_d, block_d_rot_1, block_d_rot_2].to_vec(),
-        ]
-        .to_vec();
+        let puzzle = Solver::new(2, 2, 2, None, rot_blocks_4x_2x1x1()).puzzle();
+        let mut limited = puzzle.solver();
+        limited.set_max_solutions(Some(10));
+        limited.run(Backend::MostConstrained);
+        assert_eq!(limited.solution_count(), 10);
+    }
 
-        let mut solver = Solver {
-            puzzle_height: 2,
-            puzzle_width: 2,
-            puzzle_depth: 2,
-            target: None,
-            rot_blocks,
-            stack: vec![],
-            rem: HashSet::from_iter(0..4),
-            position: 0,
-            state: vec![None; 8],
-            face_sums: [0; 6],
-            face_free_areas: [4, 4, 4, 4, 4, 4],
-            done: false,
-            solutions: HashSet::new(),
+    #[test]
+    fn side_sums_of_65_cubes_in_65x1x1() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        // too many blocks for a mask of block ids: the blocks left are not checked
+        // to complete the sides, which any stack of the cubes does
+        let cube = Block {
+            height: 1,
+            width: 1,
+            depth: 1,
+            faces: [
+                Dir::Front,
+                Dir::Back,
+                Dir::Left,
+                Dir::Right,
+                Dir::Top,
+                Dir::Bottom,
+            ]
+            .map(|dir| Face {
+                value: if matches!(dir, Dir::Top | Dir::Bottom) {
+                    65
+                } else {
+                    1
+                },
+                long: 1,
+                short: 1,
+                block: 0,
+                dir,
+            }),
+            label: "U",
         };
-        solver.init();
-        while !solver.done() && solver.step() {}
-        assert_eq!(solver.solutions.len(), 216);
+        let puzzle = Puzzle::new(
+            65,
+            1,
+            1,
+            Some(65),
+            vec![all_shape_rots(&cube); 65],
+            Symmetry::None,
+        );
+        for backend in [Backend::Backtracking, Backend::ExactCover] {
+            let mut solver = puzzle.solver();
+            solver.set_max_solutions(Some(1));
+            solver.init();
+            solver.run(backend);
+            assert_eq!(solver.solution_count(), 1);
+        }
     }
 
     #[test]
@@ -1355,23 +4031,301 @@ mod test {
 
         let rot_blocks = [block_a, block_b].iter().map(all_block_rots).collect_vec();
 
-        let mut solver = Solver {
-            puzzle_height: 2,
-            puzzle_width: 2,
-            puzzle_depth: 1,
-            target: Some(12),
-            rot_blocks,
-            stack: vec![],
-            rem: HashSet::from_iter(0..2),
-            position: 0,
-            state: vec![None; 4],
-            face_sums: [0; 6],
-            face_free_areas: [4, 4, 2, 2, 2, 2],
-            done: false,
-            solutions: HashSet::new(),
-        };
+        let mut solver = Solver::new(2, 2, 1, Some(12), rot_blocks);
         solver.init();
         while !solver.done() && solver.step() {}
         assert_eq!(solver.solutions.len(), 8);
     }
+
+    #[test]
+    fn solve_2x_2x1x1_in_2x2x1_to_sum_up_to_rotations() {
+        let mut solver = Puzzle::new(
+            2,
+            2,
+            1,
+            Some(12),
+            rot_blocks_2x_2x1x1_to_sum(),
+            Symmetry::Rotations,
+        )
+        .solver();
+        solver.init();
+        while !solver.done() && solver.step() {}
+        // the box has 8 rotations
+        assert_eq!(solver.solutions.len(), 1);
+    }
+
+    // The cells of the box as the shape-only search fills them: once as a bitset, once as the
+    // block filling each cell, as the solver held them before
+    trait Occupancy {
+        fn fits(&self, bip: &BlockInPuzzle) -> bool;
+        fn place(&mut self, bip: BlockInPuzzle);
+        fn remove(&mut self, bip: &BlockInPuzzle);
+        fn first_empty_from(&self, start: usize) -> usize;
+    }
+
+    // the cells each block rotation fills when placed at position 0
+    struct Shapes {
+        dims: [usize; 3],
+        sizes: Vec<Vec<[usize; 3]>>,
+        cells: Vec<Vec<Vec<usize>>>,
+    }
+
+    impl Shapes {
+        fn new(dims: [usize; 3], rot_blocks: &[Vec<Block>]) -> Shapes {
+            let [height, width, _] = dims;
+            let sizes = rot_blocks
+                .iter()
+                .map(|rots| {
+                    rots.iter()
+                        .map(|block| {
+                            [
+                                block.height as usize,
+                                block.width as usize,
+                                block.depth as usize,
+                            ]
+                        })
+                        .collect_vec()
+                })
+                .collect_vec();
+            let cells = sizes
+                .iter()
+                .map(|sizes| {
+                    sizes
+                        .iter()
+                        .map(|&[h, w, d]| {
+                            iproduct!(0..d, 0..w, 0..h)
+                                .map(|(z, y, x)| (z * width + y) * height + x)
+                                .collect_vec()
+                        })
+                        .collect_vec()
+                })
+                .collect_vec();
+            Shapes { dims, sizes, cells }
+        }
+
+        fn volume(&self) -> usize {
+            self.dims.iter().product()
+        }
+
+        fn masks(&self) -> Vec<Vec<BitSet>> {
+            self.cells
+                .iter()
+                .map(|cells| {
+                    cells
+                        .iter()
+                        .map(|cells| {
+                            let mut mask = BitSet::new(self.volume());
+                            for &cell in cells {
+                                mask.insert(cell);
+                            }
+                            mask.trimmed()
+                        })
+                        .collect_vec()
+                })
+                .collect_vec()
+        }
+
+        // every packing, counting the placements tried
+        fn pack(&self, occupancy: &mut impl Occupancy) -> (u64, u64) {
+            let mut used = vec![false; self.sizes.len()];
+            let (mut solutions, mut nodes) = (0, 0);
+            self.pack_from(occupancy, &mut used, 0, &mut solutions, &mut nodes);
+            (solutions, nodes)
+        }
+
+        fn pack_from(
+            &self,
+            occupancy: &mut impl Occupancy,
+            used: &mut [bool],
+            start: usize,
+            solutions: &mut u64,
+            nodes: &mut u64,
+        ) {
+            let position = occupancy.first_empty_from(start);
+            if position == self.volume() {
+                *solutions += 1;
+                return;
+            }
+            let [height, width, depth] = self.dims;
+            let (x, y, z) = (
+                position % height,
+                position / height % width,
+                position / (height * width),
+            );
+            for block_id in 0..used.len() {
+                if used[block_id] {
+                    continue;
+                }
+                for (rot_id, &[h, w, d]) in self.sizes[block_id].iter().enumerate() {
+                    let bip = BlockInPuzzle {
+                        block_id,
+                        rot_id,
+                        position,
+                    };
+                    if x + h > height || y + w > width || z + d > depth || !occupancy.fits(&bip) {
+                        continue;
+                    }
+                    *nodes += 1;
+                    occupancy.place(bip);
+                    used[block_id] = true;
+                    self.pack_from(occupancy, used, position, solutions, nodes);
+                    used[block_id] = false;
+                    occupancy.remove(&bip);
+                }
+            }
+        }
+    }
+
+    struct Bits<'a> {
+        masks: &'a [Vec<BitSet>],
+        filled: BitSet,
+    }
+
+    impl Occupancy for Bits<'_> {
+        fn fits(&self, bip: &BlockInPuzzle) -> bool {
+            !self
+                .filled
+                .intersects_at(&self.masks[bip.block_id][bip.rot_id], bip.position)
+        }
+
+        fn place(&mut self, bip: BlockInPuzzle) {
+            self.filled
+                .insert_at(&self.masks[bip.block_id][bip.rot_id], bip.position);
+        }
+
+        fn remove(&mut self, bip: &BlockInPuzzle) {
+            self.filled
+                .remove_at(&self.masks[bip.block_id][bip.rot_id], bip.position);
+        }
+
+        fn first_empty_from(&self, start: usize) -> usize {
+            self.filled.first_empty_from(start)
+        }
+    }
+
+    struct Cells<'a> {
+        shapes: &'a Shapes,
+        state: Vec<Option<BlockInPuzzle>>,
+    }
+
+    impl Cells<'_> {
+        fn cells<'b>(&'b self, bip: &BlockInPuzzle) -> impl Iterator<Item = usize> + 'b {
+            let position = bip.position;
+            self.shapes.cells[bip.block_id][bip.rot_id]
+                .iter()
+                .map(move |cell| position + cell)
+        }
+    }
+
+    impl Occupancy for Cells<'_> {
+        fn fits(&self, bip: &BlockInPuzzle) -> bool {
+            self.cells(bip).all(|cell| self.state[cell].is_none())
+        }
+
+        fn place(&mut self, bip: BlockInPuzzle) {
+            for cell in self.cells(&bip).collect_vec() {
+                self.state[cell] = Some(bip);
+            }
+        }
+
+        fn remove(&mut self, bip: &BlockInPuzzle) {
+            for cell in self.cells(bip).collect_vec() {
+                self.state[cell] = None;
+            }
+        }
+
+        fn first_empty_from(&self, start: usize) -> usize {
+            self.state[start..]
+                .iter()
+                .position(Option::is_none)
+                .map_or(self.state.len(), |offset| start + offset)
+        }
+    }
+
+    #[test]
+    fn bitset_occupancy_5x_mixed_in_4x4x5() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let shapes = Shapes::new([4, 4, 5], &rot_blocks_5x_mixed());
+        let masks = shapes.masks();
+        let bits = shapes.pack(&mut Bits {
+            masks: &masks,
+            filled: BitSet::new(shapes.volume()),
+        });
+        let cells = shapes.pack(&mut Cells {
+            shapes: &shapes,
+            state: vec![None; shapes.volume()],
+        });
+        assert_eq!(bits, (352, 2531));
+        assert_eq!(cells, bits);
+
+        let mut solver = Solver::new(4, 4, 5, None, rot_blocks_5x_mixed());
+        solver.set_retention(Retention::Nothing);
+        solver.init();
+        solver.run(Backend::Backtracking);
+        assert_eq!(
+            (solver.solution_count() as u64, solver.progress().nodes),
+            bits
+        );
+    }
+
+    // Times the shape-only search of the puzzle, without symmetry, on each occupancy;
+    // run with `cargo test --release -- --ignored --nocapture bitset_occupancy_timing`
+    #[test]
+    #[ignore]
+    fn bitset_occupancy_timing() {
+        let dims = [HEIGHT as usize, WIDTH as usize, DEPTH as usize];
+        let shapes = Shapes::new(dims, &rot_blocks(&crate::common::BLOCKS, true));
+        let masks = shapes.masks();
+
+        let start = Instant::now();
+        let bits = shapes.pack(&mut Bits {
+            masks: &masks,
+            filled: BitSet::new(shapes.volume()),
+        });
+        let bits_time = start.elapsed();
+        let start = Instant::now();
+        let cells = shapes.pack(&mut Cells {
+            shapes: &shapes,
+            state: vec![None; shapes.volume()],
+        });
+        let cells_time = start.elapsed();
+
+        assert_eq!(bits, cells);
+        assert_eq!(bits.0, 4296);
+        println!(
+            "{} packings, {} nodes: bitset {:?}, cell scan {:?}",
+            bits.0, bits.1, bits_time, cells_time
+        );
+    }
+
+    // Times the search of the puzzle with its face values in each cell order, then at the most
+    // constrained cells; run with `cargo test --release -- --ignored --nocapture value_search_timing`
+    #[test]
+    #[ignore]
+    fn value_search_timing() {
+        let search = |order, backend| {
+            let mut solver =
+                solver_in_order(false, Symmetry::Rotations, order, BlockOrder::default());
+            solver.set_retention(Retention::Nothing);
+            let start = Instant::now();
+            solver.run(backend);
+            let elapsed = start.elapsed();
+            assert_eq!(solver.solution_count(), 4);
+            (solver.progress().nodes, elapsed)
+        };
+        for (axes, reversed) in iproduct!((0..3).permutations(3), 0..8) {
+            let order = CellOrder {
+                axes: [axes[0], axes[1], axes[2]],
+                reversed: [reversed & 1 != 0, reversed & 2 != 0, reversed & 4 != 0],
+            };
+            let (nodes, elapsed) = search(order, Backend::Backtracking);
+            println!(
+                "axes {:?} reversed {:?}: {} nodes, {:?}",
+                order.axes, order.reversed, nodes, elapsed
+            );
+        }
+        let (nodes, elapsed) = search(CellOrder::default(), Backend::MostConstrained);
+        println!("most constrained: {} nodes, {:?}", nodes, elapsed);
+    }
 }